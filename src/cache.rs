@@ -0,0 +1,365 @@
+use crate::eval::{Instance, Map, Value};
+use crate::num::Number;
+use crate::parse::Cache;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+/// Defines which entry a full cache evicts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    /// Least recently used.
+    Lru,
+    /// Most recently used.
+    Mru,
+    /// Least frequently used.
+    Lfu,
+    /// Most frequently used.
+    Mfu,
+}
+
+/// Wraps the (forced) arguments of a call so that they can be used as a `HashMap` key.
+#[derive(Debug, Clone)]
+struct Key(Vec<Value>);
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Key) -> bool {
+        self.0.len() == other.0.len() && self.0.iter().zip(&other.0).all(|(a, b)| same_value(a, b))
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for value in &self.0 {
+            hash_value(value, state);
+        }
+    }
+}
+
+/// Compares values like `==`, except that numbers are compared bitwise so that `NaN` is a valid key.
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(Number::F64(a)), Value::Number(Number::F64(b))) => a.to_bits() == b.to_bits(),
+        (Value::Number(a), Value::Number(b)) => a.type_name() == b.type_name() && a.integer() == b.integer(),
+        (Value::Tuple(a), Value::Tuple(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same_value(a, b))
+        },
+        (Value::List(a), Value::List(b)) => {
+            let (a, b) = (a.borrow(), b.borrow());
+
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same_value(a, b))
+        },
+        (Value::Map(a), Value::Map(b)) => {
+            let (a, b) = (a.borrow(), b.borrow());
+
+            a.entries().len() == b.entries().len()
+                && a.entries().iter().all(|(key, value)| b.get(key).is_some_and(|other| same_value(value, other)))
+        },
+        (Value::Struct(a), Value::Struct(b)) => {
+            a.def.name == b.def.name && a.fields.len() == b.fields.len()
+                && a.fields.iter().zip(&b.fields).all(|(a, b)| same_value(a, b))
+        },
+        (a, b) => a == b
+    }
+}
+
+/// Copies the lists within a value, so that mutating them later does not change a key.
+fn snapshot(value: &Value) -> Value {
+    match value {
+        Value::List(values) => Value::List(Rc::new(RefCell::new(values.borrow().iter().map(snapshot).collect()))),
+        Value::Tuple(values) => Value::Tuple(Rc::new(values.iter().map(snapshot).collect())),
+        Value::Map(map) => {
+            let mut copy = Map::default();
+
+            for (key, value) in map.borrow().entries() {
+                copy.insert(snapshot(key), snapshot(value));
+            }
+
+            Value::Map(Rc::new(RefCell::new(copy)))
+        },
+        Value::Struct(instance) => Value::Struct(Rc::new(Instance {
+            def: instance.def.clone(),
+            fields: instance.fields.iter().map(snapshot).collect()
+        })),
+        value => value.clone()
+    }
+}
+
+fn hash_value<H: Hasher>(value: &Value, state: &mut H) {
+    std::mem::discriminant(value).hash(state);
+
+    match value {
+        Value::Unit | Value::Thunk(_) | Value::Closure(_) => (),
+        Value::Bool(b) => b.hash(state),
+        Value::Number(Number::F64(nb)) => nb.to_bits().hash(state),
+        Value::Number(nb) => nb.integer().hash(state),
+        Value::Char(ch) => ch.hash(state),
+        Value::Str(s) => s.hash(state),
+        Value::Struct(instance) => {
+            instance.def.name.hash(state);
+
+            for field in &instance.fields {
+                hash_value(field, state);
+            }
+        },
+        Value::Tuple(values) => {
+            for value in values.iter() {
+                hash_value(value, state);
+            }
+        },
+        Value::List(values) => {
+            for value in values.borrow().iter() {
+                hash_value(value, state);
+            }
+        },
+        // Equal maps may list their entries in different orders
+        Value::Map(map) => map.borrow().entries().len().hash(state),
+    }
+}
+
+/// Defines the usage statistics of a `Memo`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub occupancy: usize,
+    pub capacity: Option<usize>,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lookups = self.hits + self.misses;
+        let rate = if lookups == 0 { 0.0 } else { 100.0 * self.hits as f64 / lookups as f64 };
+
+        write!(f, "{} hits, {} misses ({:.1}% hit rate), {} evictions, ",
+               self.hits, self.misses, rate, self.evictions)?;
+
+        match self.capacity {
+            Some(capacity) => write!(f, "{}/{} entries", self.occupancy, capacity),
+            None => write!(f, "{} entries (unbounded)", self.occupancy)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    value: Value,
+    last_used: u64,
+    uses: u64,
+}
+
+/// Represents the memoization cache of a single function.
+#[derive(Debug)]
+pub struct Memo {
+    policy: Policy,
+    capacity: Option<usize>,
+    entries: HashMap<Key, Entry>,
+    tick: u64,
+    stats: Stats,
+}
+
+impl Memo {
+    /// Creates the cache declared by a function, or `None` if it is not memoized.
+    ///
+    /// A negative size (such as the default `-1` of `lru fn`) makes the cache unbounded.
+    pub fn new(cache: &Cache) -> Option<Memo> {
+        let (policy, size) = match *cache {
+            Cache::Lru(size) => (Policy::Lru, size),
+            Cache::Mru(size) => (Policy::Mru, size),
+            Cache::Lfu(size) => (Policy::Lfu, size),
+            Cache::Mfu(size) => (Policy::Mfu, size),
+            Cache::None => return None
+        };
+
+        Some(Memo {
+            policy,
+            capacity: if size < 0 { None } else { Some(size as usize) },
+            entries: HashMap::new(),
+            tick: 0,
+            stats: Stats::default(),
+        })
+    }
+
+    /// Returns the value memoized for the given arguments, if any.
+    pub fn get(&mut self, args: &[Value]) -> Option<Value> {
+        self.tick += 1;
+
+        match self.entries.get_mut(&Key(args.to_vec())) {
+            Some(entry) => {
+                entry.last_used = self.tick;
+                entry.uses += 1;
+                self.stats.hits += 1;
+
+                Some(entry.value.clone())
+            },
+
+            None => {
+                self.stats.misses += 1;
+
+                None
+            }
+        }
+    }
+
+    /// Memoizes the value of a call, evicting an entry according to the policy if the cache is full.
+    pub fn insert(&mut self, args: Vec<Value>, value: Value) {
+        let key = Key(args.iter().map(snapshot).collect());
+
+        if let Some(capacity) = self.capacity {
+            if capacity == 0 {
+                return;
+            }
+
+            if self.entries.len() >= capacity && !self.entries.contains_key(&key) {
+                if let Some(victim) = self.victim() {
+                    self.entries.remove(&victim);
+                    self.stats.evictions += 1;
+                }
+            }
+        }
+
+        self.tick += 1;
+        self.entries.insert(key, Entry { value, last_used: self.tick, uses: 1 });
+    }
+
+    /// Returns the statistics of the cache since its creation.
+    pub fn stats(&self) -> Stats {
+        Stats { occupancy: self.entries.len(), capacity: self.capacity, ..self.stats }
+    }
+
+    /// Returns the key of the entry to evict next.
+    fn victim(&self) -> Option<Key> {
+        let entries = self.entries.iter();
+
+        // Frequency ties are broken by recency, so that both orders are total.
+        let victim = match self.policy {
+            Policy::Lru => entries.min_by_key(|(_, e)| e.last_used),
+            Policy::Mru => entries.max_by_key(|(_, e)| e.last_used),
+            Policy::Lfu => entries.min_by_key(|(_, e)| (e.uses, e.last_used)),
+            Policy::Mfu => entries.max_by_key(|(_, e)| (e.uses, e.last_used)),
+        };
+
+        victim.map(|(key, _)| key.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(nb: i128) -> Value {
+        Value::Number(Number::Int(nb))
+    }
+
+    /// Creates a cache of the given policy, filled with the calls `f(1)` and `f(2)`,
+    /// after `f(1)` has been looked up `uses` more times.
+    fn filled(cache: Cache, uses: usize) -> Memo {
+        let mut memo = Memo::new(&cache).unwrap();
+
+        memo.insert(vec![int(1)], int(10));
+        memo.insert(vec![int(2)], int(20));
+
+        for _ in 0..uses {
+            assert_eq!(memo.get(&[int(1)]), Some(int(10)));
+        }
+
+        memo
+    }
+
+    /// Inserts the call `f(3)` into a full cache, and returns which of `f(1)` and `f(2)` are still cached.
+    fn survivors(mut memo: Memo) -> Vec<i128> {
+        memo.insert(vec![int(3)], int(30));
+
+        assert_eq!(memo.stats().evictions, 1);
+
+        (1..=2).filter(|&nb| memo.entries.contains_key(&Key(vec![int(nb)]))).collect()
+    }
+
+    #[test]
+    fn lru_evicts_the_least_recently_used_entry() {
+        assert_eq!(survivors(filled(Cache::Lru(2), 0)), vec![2]);
+        assert_eq!(survivors(filled(Cache::Lru(2), 1)), vec![1]);
+    }
+
+    #[test]
+    fn mru_evicts_the_most_recently_used_entry() {
+        assert_eq!(survivors(filled(Cache::Mru(2), 0)), vec![1]);
+        assert_eq!(survivors(filled(Cache::Mru(2), 1)), vec![2]);
+    }
+
+    #[test]
+    fn lfu_evicts_the_least_frequently_used_entry() {
+        assert_eq!(survivors(filled(Cache::Lfu(2), 2)), vec![1]);
+    }
+
+    #[test]
+    fn mfu_evicts_the_most_frequently_used_entry() {
+        assert_eq!(survivors(filled(Cache::Mfu(2), 2)), vec![2]);
+    }
+
+    #[test]
+    fn frequency_ties_are_broken_by_recency() {
+        assert_eq!(survivors(filled(Cache::Lfu(2), 0)), vec![2]);
+        assert_eq!(survivors(filled(Cache::Mfu(2), 0)), vec![1]);
+    }
+
+    #[test]
+    fn stats_count_hits_misses_and_evictions() {
+        let mut memo = filled(Cache::Lru(2), 2);
+
+        assert_eq!(memo.get(&[int(3)]), None);
+        memo.insert(vec![int(3)], int(30));
+
+        assert_eq!(memo.stats(), Stats { hits: 2, misses: 1, evictions: 1, occupancy: 2, capacity: Some(2) });
+        assert_eq!(memo.stats().to_string(), "2 hits, 1 misses (66.7% hit rate), 1 evictions, 2/2 entries");
+    }
+
+    #[test]
+    fn unbounded_caches_never_evict() {
+        let mut memo = Memo::new(&Cache::Lru(-1)).unwrap();
+
+        for nb in 0..100 {
+            memo.insert(vec![int(nb)], int(nb));
+        }
+
+        assert_eq!(memo.stats().evictions, 0);
+        assert_eq!(memo.stats().occupancy, 100);
+        assert_eq!(memo.stats().capacity, None);
+    }
+
+    #[test]
+    fn empty_caches_store_nothing() {
+        let mut memo = Memo::new(&Cache::Lfu(0)).unwrap();
+
+        memo.insert(vec![int(1)], int(10));
+
+        assert_eq!(memo.get(&[int(1)]), None);
+        assert_eq!(memo.stats().occupancy, 0);
+    }
+
+    #[test]
+    fn updating_an_entry_does_not_evict() {
+        let mut memo = filled(Cache::Lru(2), 0);
+
+        memo.insert(vec![int(2)], int(21));
+
+        assert_eq!(memo.get(&[int(2)]), Some(int(21)));
+        assert_eq!(memo.stats().evictions, 0);
+    }
+
+    #[test]
+    fn keys_compare_floats_bitwise_and_numbers_by_type() {
+        let mut memo = Memo::new(&Cache::Lru(-1)).unwrap();
+
+        memo.insert(vec![Value::Number(Number::F64(f64::NAN))], int(1));
+        memo.insert(vec![Value::Number(Number::U8(1))], int(2));
+
+        assert_eq!(memo.get(&[Value::Number(Number::F64(f64::NAN))]), Some(int(1)));
+        assert_eq!(memo.get(&[Value::Number(Number::U64(1))]), None);
+    }
+}
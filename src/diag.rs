@@ -0,0 +1,232 @@
+use crate::lex::Span;
+use std::fmt;
+use std::io::IsTerminal;
+
+/// Code of the diagnostics reported by the `Lexer`.
+pub const LEX_ERROR: &str = "E0001";
+/// Code of the diagnostics reported by the `Parser`.
+pub const SYNTAX_ERROR: &str = "E0002";
+/// Code of the diagnostics reported while evaluating a program.
+pub const RUNTIME_ERROR: &str = "E0003";
+/// Code of the errors about a `match` that does not cover every value.
+pub const NON_EXHAUSTIVE_MATCH: &str = "E0004";
+/// Code of the errors about a pattern that does not fit the struct it names.
+pub const INVALID_PATTERN: &str = "E0005";
+/// Code of the errors about expressions whose types do not fit together.
+pub const TYPE_ERROR: &str = "E0006";
+/// Code of the warnings about a `match` arm that can never be selected.
+pub const UNREACHABLE_ARM: &str = "W0001";
+/// Code of the warnings about a value that is computed eagerly where a thunk is expected.
+pub const EAGER_VALUE: &str = "W0002";
+
+/// Defines how serious a `Diagnostic` is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Defines a secondary location attached to a `Diagnostic`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// Defines what a `Diagnostic` shows below its source snippet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Details {
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+/// Represents a message reported to the user about their source code.
+///
+/// The details are boxed, so that the results that may fail with a diagnostic stay small.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub span: Span,
+    pub details: Box<Details>,
+}
+
+impl Diagnostic {
+    /// Creates a new diagnostic, given its severity, message and primary location.
+    pub fn new(severity: Severity, message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code: None,
+            message: message.into(),
+            span,
+            details: Box::default(),
+        }
+    }
+
+    /// Creates a new error.
+    pub fn error(message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic::new(Severity::Error, message, span)
+    }
+
+    /// Creates a new warning.
+    pub fn warning(message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, message, span)
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Diagnostic {
+        self.code = Some(code);
+        self
+    }
+
+    /// Points at another location relevant to the diagnostic.
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.details.labels.push(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.details.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Diagnostic {
+        self.details.help = Some(help.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Renders the diagnostic along with the lines of `source` it points at,
+    /// using ANSI colors if `color` is set.
+    pub fn render(&self, source: &str, file: &str, color: bool) -> String {
+        let paint = Painter { color };
+        let mut out = String::new();
+
+        let style = match self.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        };
+
+        let header = match self.code {
+            Some(code) => format!("{}[{}]", self.severity, code),
+            None => self.severity.to_string(),
+        };
+
+        out += &format!("{}{}\n", paint.bold(style, &header), paint.bold("", &format!(": {}", self.message)));
+
+        // Gather every underlined location, the primary one first
+        let mut marks = vec![(self.span, None, true)];
+
+        marks.extend(self.details.labels.iter().map(|label| (label.span, Some(label.message.as_str()), false)));
+        marks.sort_by_key(|(span, _, _)| (span.line, span.col));
+
+        let lines = source.lines().collect::<Vec<_>>();
+        let gutter = marks.iter().map(|(span, _, _)| span.line).max().unwrap_or(1).to_string().len();
+        let pad = " ".repeat(gutter);
+
+        out += &format!("{}{} {}:{}:{}\n", pad, paint.bold(BLUE, "-->"), file, self.span.line, self.span.col);
+        out += &format!("{} {}\n", pad, paint.bold(BLUE, "|"));
+
+        let mut previous_line = None;
+
+        for (span, message, primary) in marks {
+            let text = match lines.get(span.line.saturating_sub(1)) {
+                Some(text) => *text,
+                None => ""
+            };
+
+            if previous_line != Some(span.line) {
+                if previous_line.is_some_and(|line| line + 1 < span.line) {
+                    out += &format!("{}\n", paint.bold(BLUE, "..."));
+                }
+
+                out += &format!("{:>width$} {} {}\n", paint.bold(BLUE, &span.line.to_string()),
+                                paint.bold(BLUE, "|"), text, width = gutter + paint.overhead(BLUE));
+                previous_line = Some(span.line);
+            }
+
+            // Keep tabs in the indentation, so that the underline is aligned with the text
+            let indent = text.chars()
+                             .take(span.col.saturating_sub(1))
+                             .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+                             .collect::<String>();
+
+            let width = text.chars().skip(span.col.saturating_sub(1)).count()
+                            .min(span.end.saturating_sub(span.start))
+                            .max(1);
+
+            let (marker, marker_style) = if primary { ('^', style) } else { ('-', BLUE) };
+            let underline = marker.to_string().repeat(width);
+            let message = match message {
+                Some(message) => format!(" {}", message),
+                None => String::new()
+            };
+
+            out += &format!("{} {} {}{}\n", pad, paint.bold(BLUE, "|"), indent,
+                            paint.bold(marker_style, &(underline + &message)));
+        }
+
+        for note in &self.details.notes {
+            out += &format!("{} {} {}: {}\n", pad, paint.bold(BLUE, "="), paint.bold("", "note"), note);
+        }
+
+        if let Some(ref help) = self.details.help {
+            out += &format!("{} {} {}: {}\n", pad, paint.bold(BLUE, "="), paint.bold("", "help"), help);
+        }
+
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    /// Formats the diagnostic on a single line, without any source snippet.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "{}[{}] at {}: {}", self.severity, code, self.span, self.message),
+            None => write!(f, "{} at {}: {}", self.severity, self.span, self.message),
+        }
+    }
+}
+
+/// Returns whether diagnostics printed to the standard error should be colored,
+/// which is the case when it is a terminal and the `NO_COLOR` variable is not set.
+pub fn color_enabled() -> bool {
+    std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal()
+}
+
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const BLUE: &str = "\x1b[34m";
+
+/// Wraps text in ANSI escape sequences, unless colors are disabled.
+struct Painter {
+    color: bool,
+}
+
+impl Painter {
+    fn bold(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[1m{}{}\x1b[0m", style, text)
+        } else {
+            text.to_owned()
+        }
+    }
+
+    /// Returns the number of invisible characters `bold` adds with the given style.
+    fn overhead(&self, style: &str) -> usize {
+        if self.color { style.len() + 8 } else { 0 }
+    }
+}
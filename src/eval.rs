@@ -0,0 +1,1485 @@
+use crate::cache::{Memo, Stats};
+use crate::diag::{Diagnostic, RUNTIME_ERROR};
+use crate::exhaust;
+use crate::typeck::{self, Context};
+use crate::lex::Span;
+use crate::num::{Number, NUMERIC_TYPES};
+use crate::parse::{Arm, Expr, ExprKind, Fields, Function, Item, Member, Pattern, PatternKind, Program, Stmt, Struct, Type};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+/// Maximum number of nested calls, including the forcing of thunks, before evaluation fails.
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+/// Size of the stack evaluation must run on for `MAX_CALL_DEPTH` nested calls to fit in it,
+/// since every call recurses through the evaluator.
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// Defines a value produced by evaluating an `Expr`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    Closure(Rc<Closure>),
+    List(List),
+    Map(Rc<RefCell<Map>>),
+    Number(Number),
+    Char(char),
+    Str(String),
+    Struct(Rc<Instance>),
+    Thunk(Thunk),
+    Tuple(Rc<Vec<Value>>),
+}
+
+/// Defines a growable list, which is shared rather than copied.
+pub type List = Rc<RefCell<Vec<Value>>>;
+
+/// Defines a map, which is shared rather than copied and iterated in insertion order.
+#[derive(Debug, Clone, Default)]
+pub struct Map {
+    entries: Vec<(Value, Value)>
+}
+
+impl Map {
+    /// Returns the entries of the map, in insertion order.
+    pub fn entries(&self) -> &[(Value, Value)] {
+        &self.entries
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, value)| value)
+    }
+
+    /// Inserts or updates an entry, returning the value it replaced if any.
+    /// An updated entry keeps its position.
+    pub fn insert(&mut self, key: Value, value: Value) -> Option<Value> {
+        match self.entries.iter_mut().find(|(k, _)| *k == key) {
+            Some(entry) => Some(std::mem::replace(&mut entry.1, value)),
+            None => {
+                self.entries.push((key, value));
+
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &Value) -> Option<Value> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+
+        Some(self.entries.remove(index).1)
+    }
+}
+
+// Maps are equal when they have the same entries, regardless of their order.
+impl PartialEq for Map {
+    fn eq(&self, other: &Map) -> bool {
+        self.entries.len() == other.entries.len()
+            && self.entries.iter().all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl fmt::Display for Map {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.entries.is_empty() {
+            return write!(f, "{{=>}}");
+        }
+
+        write!(f, "{{")?;
+
+        for (i, (key, value)) in self.entries.iter().enumerate() {
+            write!(f, "{}{} => {}", if i == 0 { "" } else { ", " }, key, value)?;
+        }
+
+        write!(f, "}}")
+    }
+}
+
+/// Defines an instance of a `struct`, whose fields are stored in declaration order.
+#[derive(Debug)]
+pub struct Instance {
+    pub def: Rc<Struct>,
+    pub fields: Vec<Value>,
+}
+
+impl Instance {
+    /// Returns the value of a member of the instance, if its struct has it.
+    pub fn get(&self, member: &Member) -> Option<&Value> {
+        self.def.position(member).map(|index| &self.fields[index])
+    }
+}
+
+impl PartialEq for Instance {
+    fn eq(&self, other: &Instance) -> bool {
+        self.def.name == other.def.name && self.fields == other.fields
+    }
+}
+
+impl fmt::Display for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.def.name)?;
+
+        match self.def.fields {
+            Fields::Named(ref names) => {
+                write!(f, " {{")?;
+
+                for (i, ((name, _), value)) in names.iter().zip(&self.fields).enumerate() {
+                    write!(f, "{} {}: {}", if i == 0 { "" } else { "," }, name, value)?;
+                }
+
+                write!(f, " }}")
+            },
+
+            Fields::Tuple(_) => {
+                write!(f, "(")?;
+
+                for (i, value) in self.fields.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { "" } else { ", " }, value)?;
+                }
+
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Defines a closure, along with the scope it captured when it was created.
+pub struct Closure {
+    pub params: Vec<String>,
+    pub body: Rc<Expr>,
+    pub env: Rc<Env>,
+    pub span: Span,
+}
+
+// Closures are compared by identity, since their bodies cannot be compared.
+impl PartialEq for Closure {
+    fn eq(&self, other: &Closure) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+// The captured scope is left out, as it may contain the closure itself.
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Closure").field("params", &self.params).field("span", &self.span).finish()
+    }
+}
+
+/// Defines the state of a lazy computation.
+#[derive(Debug)]
+enum ThunkState {
+    /// A deferred call to a function, with its arguments already bound.
+    Call { fn_name: String, args: Vec<Value>, span: Span },
+
+    /// A deferred call to a closure, which may itself still be a thunk.
+    Apply { callee: Value, args: Vec<Value>, span: Span },
+
+    /// A deferred `&expr`, along with the scope it was created in.
+    Expr { expr: Rc<Expr>, env: Rc<Env> },
+
+    /// The thunk is currently being forced.
+    Forcing,
+
+    Forced(Value),
+}
+
+/// Represents a lazy computation, which is run at most once.
+#[derive(Debug, Clone)]
+pub struct Thunk(Rc<RefCell<ThunkState>>);
+
+impl PartialEq for Thunk {
+    fn eq(&self, other: &Thunk) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Creates a thunk value, given the computation it defers.
+fn delay(state: ThunkState) -> Value {
+    Value::Thunk(Thunk(Rc::new(RefCell::new(state))))
+}
+
+impl Value {
+    /// Returns whether the value is considered true in a condition.
+    fn truthy(&self) -> Result<bool, String> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            Value::Number(nb) => Ok(!nb.is_zero()),
+            v => Err(format!("Expected a condition but found {}.", v))
+        }
+    }
+
+    fn as_number(&self) -> Result<Number, String> {
+        match self {
+            Value::Number(nb) => Ok(*nb),
+            v => Err(format!("Expected a number but found {}.", v))
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Closure(closure) => write!(f, "<closure/{}>", closure.params.len()),
+            Value::Map(map) => write!(f, "{}", map.borrow()),
+            Value::List(values) => {
+                write!(f, "[")?;
+
+                for (i, value) in values.borrow().iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { "" } else { ", " }, value)?;
+                }
+
+                write!(f, "]")
+            },
+            Value::Number(nb) => write!(f, "{}", nb),
+            Value::Char(ch) => write!(f, "{}", ch),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Struct(instance) => write!(f, "{}", instance),
+            Value::Tuple(values) => {
+                write!(f, "(")?;
+
+                for (i, value) in values.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { "" } else { ", " }, value)?;
+                }
+
+                // Distinguish 1-tuples from parenthesized values
+                write!(f, "{})", if values.len() == 1 { "," } else { "" })
+            },
+            Value::Thunk(thunk) => match *thunk.0.borrow() {
+                ThunkState::Forced(ref value) => write!(f, "{}", value),
+                _ => write!(f, "<thunk>")
+            }
+        }
+    }
+}
+
+/// Defines the location that a `target[index]` expression refers to.
+enum Slot {
+    /// An element of a list, whose index is within bounds.
+    Element(List, usize),
+
+    /// The entry of a map for a key, which may be missing.
+    Entry(Rc<RefCell<Map>>, Value),
+}
+
+/// Defines the result of an evaluation; namely a `Value` on success,
+/// or a `Diagnostic` on failure.
+pub type EvalResult = Result<Value, Diagnostic>;
+
+/// Defines a native function that can be bound by an `extern` declaration.
+///
+/// Its errors are located at the call site by the `Evaluator`.
+pub type Builtin = fn(&[Value]) -> Result<Value, String>;
+
+/// Defines a lexical scope, binding variable names to their values.
+#[derive(Debug, Default)]
+pub struct Env {
+    vars: RefCell<HashMap<String, Value>>,
+    parent: Option<Rc<Env>>
+}
+
+impl Env {
+    /// Creates a new scope nested in `parent`.
+    pub fn with_parent(parent: &Rc<Env>) -> Rc<Env> {
+        Rc::new(Env { vars: RefCell::new(HashMap::new()), parent: Some(parent.clone()) })
+    }
+
+    /// Binds `name` in this scope, shadowing any outer binding.
+    pub fn define(&self, name: &str, value: Value) {
+        self.vars.borrow_mut().insert(name.to_owned(), value);
+    }
+
+    /// Looks `name` up in this scope and its parents.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match self.vars.borrow().get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.parent.as_ref().and_then(|parent| parent.get(name))
+        }
+    }
+
+    /// Updates the innermost existing binding of `name`, returning `false` if there is none.
+    pub fn assign(&self, name: &str, value: Value) -> bool {
+        if let Some(slot) = self.vars.borrow_mut().get_mut(name) {
+            *slot = value;
+            return true;
+        }
+
+        match self.parent {
+            Some(ref parent) => parent.assign(name, value),
+            None => false
+        }
+    }
+}
+
+/// Represents the tree-walking evaluator.
+///
+/// Definitions and global variables persist between calls to `eval`,
+/// which allows a REPL to define a function on a line and call it on the next.
+pub struct Evaluator {
+    functions: HashMap<String, Rc<Function>>,
+    structs: HashMap<String, Rc<Struct>>,
+    builtins: HashMap<String, Builtin>,
+    caches: HashMap<String, Memo>,
+    globals: Rc<Env>,
+    depth: usize
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Evaluator {
+    /// Creates a new evaluator, with the native functions registered.
+    pub fn new() -> Self {
+        let mut builtins: HashMap<&'static str, Builtin> = HashMap::new();
+
+        builtins.insert("print,", builtin_print);
+        builtins.insert("println,", builtin_println);
+        builtins.insert("unary-", builtin_neg);
+        builtins.insert("unary!", builtin_not);
+        builtins.insert("sqrt", |args| Ok(Value::Number(Number::F64(number_arg(args)?.to_f64().sqrt()))));
+        builtins.insert("sin", |args| Ok(Value::Number(Number::F64(number_arg(args)?.to_f64().sin()))));
+        builtins.insert("cos", |args| Ok(Value::Number(Number::F64(number_arg(args)?.to_f64().cos()))));
+        builtins.insert("u8", |args| Ok(Value::Number(number_arg(args)?.cast("u8")?)));
+        builtins.insert("u64", |args| Ok(Value::Number(number_arg(args)?.cast("u64")?)));
+        builtins.insert("i64", |args| Ok(Value::Number(number_arg(args)?.cast("i64")?)));
+        builtins.insert("f64", |args| Ok(Value::Number(number_arg(args)?.cast("f64")?)));
+        builtins.insert("len", builtin_len);
+        builtins.insert("push", builtin_push);
+        builtins.insert("pop", builtin_pop);
+        builtins.insert("contains", builtin_contains);
+        builtins.insert("insert", builtin_insert);
+        builtins.insert("remove", builtin_remove);
+        builtins.insert("keys", builtin_keys);
+        builtins.insert("values", builtin_values);
+        builtins.insert("entries", builtin_entries);
+        builtins.insert("std::stdio::getline", builtin_getline);
+
+        Evaluator {
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            builtins: builtins.into_iter().map(|(name, builtin)| (name.to_owned(), builtin)).collect(),
+            caches: HashMap::new(),
+            globals: Rc::new(Env::default()),
+            depth: 0
+        }
+    }
+
+    /// Evaluates a parsed `Item`.
+    ///
+    /// Definitions, declarations and imports are registered and yield `None`, while
+    /// anonymous top-level functions are run and yield their motivated value.
+    pub fn eval(&mut self, item: Item) -> Result<Option<Value>, Diagnostic> {
+        match item {
+            Item::Function(function) => self.eval_function(function),
+
+            Item::Struct(def) => {
+                self.structs.insert(def.name.clone(), Rc::new(def));
+
+                Ok(None)
+            },
+
+            Item::Ext { path, span } => {
+                let full_name = path.join("::");
+
+                let builtin = match self.builtins.get(&full_name) {
+                    Some(builtin) => *builtin,
+                    None => return Err(error(format!("Unknown external function '{}'.", full_name), span))
+                };
+
+                // Import the function under its last path segment
+                self.builtins.insert(path[path.len() - 1].clone(), builtin);
+
+                Ok(None)
+            }
+        }
+    }
+
+    /// Returns the diagnostics of the static analysis of an item, which must pass
+    /// before the item is evaluated. The item can refer to itself, like a recursive function.
+    pub fn analyze(&self, item: &Item) -> Vec<Diagnostic> {
+        self.check(item, &self.context(std::slice::from_ref(item)))
+    }
+
+    /// Returns the inferred type of an expression, which can refer to the registered definitions.
+    pub fn type_of(&self, expr: &Expr) -> Result<String, Vec<Diagnostic>> {
+        typeck::type_of(expr, &self.context(&[]))
+    }
+
+    /// Returns the definitions that the given items can refer to, which are
+    /// the registered ones along with the items themselves.
+    fn context<'a>(&'a self, items: &'a [Item]) -> Context<'a> {
+        let mut structs = self.structs
+                              .iter()
+                              .map(|(name, def)| (name.as_str(), &**def))
+                              .collect::<HashMap<_, _>>();
+        let mut functions = self.functions
+                                .iter()
+                                .map(|(name, function)| (name.as_str(), &function.prototype))
+                                .collect::<HashMap<_, _>>();
+        let mut builtins = self.builtins.keys().map(String::as_str).collect::<HashSet<_>>();
+
+        for item in items {
+            match item {
+                Item::Struct(def) => { structs.insert(&def.name, def); },
+                Item::Function(function) if !function.is_anon => {
+                    functions.insert(&function.prototype.name, &function.prototype);
+                },
+                // Imports are registered under the last segment of their path
+                Item::Ext { path, .. } => { builtins.insert(&path[path.len() - 1]); },
+                _ => ()
+            }
+        }
+
+        Context { structs, functions, builtins }
+    }
+
+    /// Runs the static analyses of an item: type checking, then exhaustiveness checking.
+    fn check(&self, item: &Item, context: &Context) -> Vec<Diagnostic> {
+        match item {
+            Item::Function(function) => {
+                let mut diagnostics = typeck::check_function(function, context);
+
+                diagnostics.extend(exhaust::check_function(function, &context.structs));
+                diagnostics
+            },
+            Item::Struct(def) => typeck::check_struct(def, context),
+            Item::Ext { .. } => vec![]
+        }
+    }
+
+    /// Analyzes and registers every item of a program, returning the diagnostics
+    /// reported along the way. Items are only registered if there are no errors.
+    pub fn load(&mut self, program: Program) -> Vec<Diagnostic> {
+        // Items can use the definitions that follow them, so they are all analyzed before any is registered
+        let mut diagnostics = {
+            let context = self.context(&program.items);
+
+            program.items.iter().flat_map(|item| self.check(item, &context)).collect::<Vec<_>>()
+        };
+
+        for item in program.items {
+            if diagnostics.iter().any(Diagnostic::is_error) {
+                break;
+            }
+
+            if let Err(err) = self.eval(item) {
+                diagnostics.push(err);
+            }
+        }
+
+        diagnostics
+    }
+
+    /// Calls the function `name` without arguments and motivates its result,
+    /// or returns `None` if there is no such function.
+    pub fn run(&mut self, name: &str) -> Option<EvalResult> {
+        let span = self.functions.get(name)?.prototype.span;
+
+        Some(self.call(name, vec![], span).and_then(|value| self.force(value, span)))
+    }
+
+    fn eval_function(&mut self, function: Function) -> Result<Option<Value>, Diagnostic> {
+        if function.is_anon {
+            let globals = self.globals.clone();
+
+            return match function.body {
+                Some(ref body) => self.eval_strict(body, &globals).map(Some),
+                None => Ok(None)
+            };
+        }
+
+        let name = function.prototype.name.clone();
+
+        if function.body.is_none() && !self.builtins.contains_key(&name) {
+            return Err(error(format!("Unknown external function '{}'.", name), function.prototype.span)
+                .with_help("only the functions provided by the runtime can be declared 'extern'"));
+        }
+
+        // Redefining a function discards the results memoized for its previous body.
+        match Memo::new(&function.cache) {
+            Some(memo) => self.caches.insert(name.clone(), memo),
+            None => self.caches.remove(&name)
+        };
+
+        self.functions.insert(name, Rc::new(function));
+
+        Ok(None)
+    }
+
+    /// Returns the cache statistics of the memoized function `name`.
+    pub fn cache_stats(&self, name: &str) -> Option<Stats> {
+        self.caches.get(name).map(Memo::stats)
+    }
+
+    /// Returns the cache statistics of every memoized function, sorted by name.
+    pub fn cache_report(&self) -> Vec<(&str, Stats)> {
+        let mut report = self.caches.iter()
+                                    .map(|(name, memo)| (name.as_str(), memo.stats()))
+                                    .collect::<Vec<_>>();
+
+        report.sort_by_key(|(name, _)| *name);
+        report
+    }
+
+    /// Evaluates an expression in the given scope.
+    fn eval_expr(&mut self, expr: &Expr, env: &Rc<Env>) -> EvalResult {
+        match &expr.kind {
+            ExprKind::Bool(b) => Ok(Value::Bool(*b)),
+            ExprKind::Int(nb, None) => Ok(Value::Number(Number::Int(*nb))),
+            ExprKind::Int(nb, Some(suffix)) => Ok(Value::Number(located(Number::Int(*nb).cast(suffix), expr.span)?)),
+            ExprKind::Float(nb) => Ok(Value::Number(Number::F64(*nb))),
+            ExprKind::Char(ch) => Ok(Value::Char(*ch)),
+            ExprKind::Str(s) => Ok(Value::Str(s.clone())),
+
+            ExprKind::Variable(name) => match env.get(name) {
+                Some(value) => Ok(value),
+                None => Err(error(format!("Unknown variable '{}'.", name), expr.span))
+            },
+
+            ExprKind::Binary { op, left, right } if op == "=" => {
+                let name = match left.kind {
+                    ExprKind::Variable(ref name) => name,
+                    ExprKind::Index { expr: ref target, ref index } => {
+                        let slot = self.eval_index(target, index, env)?;
+                        let value = self.eval_strict(right, env)?;
+
+                        // Assigning to a missing key inserts it
+                        match slot {
+                            Slot::Element(values, i) => {
+                                let mut values = values.borrow_mut();
+                                let len = values.len();
+
+                                // The right-hand side may have shrunk the list since the index was checked
+                                match values.get_mut(i) {
+                                    Some(element) => *element = value.clone(),
+                                    None => return Err(error(format!("Index {} is out of bounds for a list of {} element(s).", i, len), index.span)
+                                        .with_label(right.span, "list shrunk by the assigned value"))
+                                }
+                            },
+                            Slot::Entry(map, key) => { map.borrow_mut().insert(key, value.clone()); }
+                        }
+
+                        return Ok(value);
+                    },
+                    _ => return Err(error("Expected variable or index on the left side of '='.", left.span))
+                };
+
+                let value = self.eval_expr(right, env)?;
+
+                if env.assign(name, value.clone()) {
+                    Ok(value)
+                } else {
+                    Err(error(format!("Unknown variable '{}'.", name), left.span))
+                }
+            },
+
+            // Logical operators only evaluate their right-hand side if needed
+            ExprKind::Binary { op, left, right } if op == "&&" || op == "||" => {
+                let value = located(self.eval_strict(left, env)?.truthy(), left.span)?;
+
+                if value == (op == "||") {
+                    return Ok(Value::Bool(value));
+                }
+
+                let value = located(self.eval_strict(right, env)?.truthy(), right.span)?;
+
+                Ok(Value::Bool(value))
+            },
+
+            ExprKind::Binary { op, left, right } => {
+                let left = self.eval_strict(left, env)?;
+                let right = self.eval_strict(right, env)?;
+
+                self.eval_binary(op, left, right, expr.span)
+            },
+
+            ExprKind::Call { fn_name, args, bang } => {
+                let args = args.iter()
+                               .map(|arg| self.eval_expr(arg, env))
+                               .collect::<Result<Vec<_>, _>>()?;
+
+                // Variables shadow functions, so that closures can be called by name
+                if let Some(callee) = env.get(fn_name) {
+                    return if *bang {
+                        self.call_value(callee, args, expr.span)
+                    } else {
+                        Ok(delay(ThunkState::Apply { callee, args, span: expr.span }))
+                    };
+                }
+
+                if *bang {
+                    self.call(fn_name, args, expr.span)
+                } else {
+                    Ok(delay(ThunkState::Call { fn_name: fn_name.clone(), args, span: expr.span }))
+                }
+            },
+
+            ExprKind::Apply { callee, args, bang } => {
+                let callee = self.eval_expr(callee, env)?;
+                let args = args.iter()
+                               .map(|arg| self.eval_expr(arg, env))
+                               .collect::<Result<Vec<_>, _>>()?;
+
+                if *bang {
+                    self.call_value(callee, args, expr.span)
+                } else {
+                    Ok(delay(ThunkState::Apply { callee, args, span: expr.span }))
+                }
+            },
+
+            // Methods are functions whose first parameter is the receiver
+            ExprKind::MethodCall { receiver, method, args, bang } => {
+                let args = std::iter::once(&**receiver)
+                               .chain(args)
+                               .map(|arg| self.eval_expr(arg, env))
+                               .collect::<Result<Vec<_>, _>>()?;
+
+                if *bang {
+                    self.call(method, args, expr.span)
+                } else {
+                    Ok(delay(ThunkState::Call { fn_name: method.clone(), args, span: expr.span }))
+                }
+            },
+
+            ExprKind::Closure { params, body } => Ok(Value::Closure(Rc::new(Closure {
+                params: params.clone(),
+                body: body.clone(),
+                env: env.clone(),
+                span: expr.span
+            }))),
+
+            ExprKind::StructLit { name, fields } => {
+                let def = match self.structs.get(name) {
+                    Some(def) => def.clone(),
+                    None => return Err(error(format!("Unknown struct '{}'.", name), expr.span))
+                };
+
+                let names = match def.fields {
+                    Fields::Named(ref names) => names,
+                    Fields::Tuple(_) => return Err(error(format!("Struct '{}' has positional fields.", name), expr.span)
+                        .with_label(def.span, "struct defined here")
+                        .with_help(format!("use '{}(...)' to build it", name)))
+                };
+
+                let mut values = vec![None; names.len()];
+
+                for (field, init) in fields {
+                    let index = match names.iter().position(|(name, _)| name == field) {
+                        Some(index) => index,
+                        None => return Err(error(format!("Struct '{}' has no field '{}'.", name, field), init.span)
+                            .with_label(def.span, "struct defined here"))
+                    };
+
+                    if values[index].is_some() {
+                        return Err(error(format!("Field '{}' is initialized twice.", field), init.span));
+                    }
+
+                    let value = self.eval_strict(init, env)?;
+
+                    values[index] = Some(located(typed(value, &names[index].1), init.span)?);
+                }
+
+                let missing = names.iter()
+                                   .zip(&values)
+                                   .filter(|(_, value)| value.is_none())
+                                   .map(|((name, _), _)| format!("'{}'", name))
+                                   .collect::<Vec<_>>();
+
+                if !missing.is_empty() {
+                    return Err(error(format!("Missing field(s) {} in struct literal.", missing.join(", ")), expr.span)
+                        .with_label(def.span, "struct defined here"));
+                }
+
+                Ok(Value::Struct(Rc::new(Instance { def, fields: values.into_iter().flatten().collect() })))
+            },
+
+            ExprKind::Tuple(elements) => {
+                if elements.is_empty() {
+                    return Ok(Value::Unit);
+                }
+
+                // Like the elements of a list, the elements of a tuple are forced, so that tuples compare by value
+                let values = elements.iter()
+                                     .map(|element| self.eval_strict(element, env))
+                                     .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(Value::Tuple(Rc::new(values)))
+            },
+
+            // Like struct fields, the elements of a list are forced
+            ExprKind::List(elements) => {
+                let values = elements.iter()
+                                     .map(|element| self.eval_strict(element, env))
+                                     .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(Value::List(Rc::new(RefCell::new(values))))
+            },
+
+            ExprKind::Map(entries) => {
+                let mut map = Map::default();
+
+                // Like list elements, keys and values are forced, and later entries override earlier ones
+                for (key, value) in entries {
+                    let key = self.eval_strict(key, env)?;
+                    let value = self.eval_strict(value, env)?;
+
+                    map.insert(key, value);
+                }
+
+                Ok(Value::Map(Rc::new(RefCell::new(map))))
+            },
+
+            ExprKind::Index { expr: target, index: key } => match self.eval_index(target, key, env)? {
+                Slot::Element(values, index) => {
+                    let value = values.borrow()[index].clone();
+
+                    Ok(value)
+                },
+
+                Slot::Entry(map, key_value) => {
+                    let value = map.borrow().get(&key_value).cloned();
+
+                    value.ok_or_else(|| error(format!("Key {} is not in the map.", key_value), key.span)
+                        .with_label(target.span, "map indexed here"))
+                }
+            },
+
+            ExprKind::Field { expr: target, member } => {
+                let value = self.eval_strict(target, env)?;
+
+                let instance = match value {
+                    Value::Struct(ref instance) => instance,
+
+                    Value::Tuple(ref values) => return match member {
+                        Member::Index(index) if *index < values.len() => Ok(values[*index].clone()),
+                        _ => Err(error(format!("Tuple of {} element(s) has no field '{}'.", values.len(), member), expr.span))
+                    },
+
+                    ref value => return Err(error(format!("Expected a struct or a tuple but found {}.", value), target.span))
+                };
+
+                match instance.get(member) {
+                    Some(value) => Ok(value.clone()),
+                    None => Err(error(format!("Struct '{}' has no field '{}'.", instance.def.name, member), expr.span)
+                        .with_label(instance.def.span, "struct defined here"))
+                }
+            },
+
+            ExprKind::Match { expr: scrutinee, arms } => {
+                let value = self.eval_strict(scrutinee, env)?;
+
+                match self.select_arm(arms, &value, env)? {
+                    Some((arm, scope)) => self.eval_expr(&arm.body, &scope),
+                    None => Err(error(format!("No arm matches the value {}.", value), expr.span)
+                        .with_label(scrutinee.span, "value matched here"))
+                }
+            },
+
+            ExprKind::Block { stmts, tail } => {
+                let mut scope = Env::with_parent(env);
+
+                for stmt in stmts {
+                    match stmt {
+                        Stmt::Let { name, ty, value: init } => {
+                            let mut value = self.eval_expr(init, &scope)?;
+
+                            if let Some(ty) = ty {
+                                value = located(typed(value, ty), init.span)?;
+                            }
+
+                            // Every binding opens a new scope, so that thunks created
+                            // before a shadowing binding still see the previous value.
+                            scope = Env::with_parent(&scope);
+                            scope.define(name, value);
+                        },
+
+                        Stmt::Expr(expr) => {
+                            self.eval_strict(expr, &scope)?;
+                        }
+                    }
+                }
+
+                match tail {
+                    Some(tail) => self.eval_expr(tail, &scope),
+                    None => Ok(Value::Unit)
+                }
+            },
+
+            ExprKind::Lazy(expr) => Ok(delay(ThunkState::Expr { expr: expr.clone(), env: env.clone() })),
+
+            ExprKind::Mot(expr) => self.eval_strict(expr, env),
+
+            ExprKind::Conditional { cond, consequence, alternative } => {
+                if located(self.eval_strict(cond, env)?.truthy(), cond.span)? {
+                    self.eval_expr(consequence, env)
+                } else {
+                    self.eval_expr(alternative, env)
+                }
+            },
+
+            ExprKind::For { var_name, start, end, step, body } => {
+                let scope = Env::with_parent(env);
+                let start = self.eval_expr(start, &scope)?;
+
+                scope.define(var_name, start);
+
+                while located(self.eval_strict(end, &scope)?.truthy(), end.span)? {
+                    self.eval_strict(body, &scope)?;
+
+                    let step = match step {
+                        Some(step) => self.eval_strict(step, &scope)?,
+                        None => Value::Number(Number::Int(1))
+                    };
+                    let current = self.force(scope.get(var_name).unwrap_or(Value::Unit), expr.span)?;
+
+                    scope.assign(var_name, self.eval_binary("+", current, step, expr.span)?);
+                }
+
+                Ok(Value::Unit)
+            },
+
+            ExprKind::VarIn { variables, body } => {
+                let scope = Env::with_parent(env);
+
+                for (name, initializer) in variables {
+                    let value = match initializer {
+                        Some(init) => self.eval_expr(init, &scope)?,
+                        None => Value::Number(Number::Int(0))
+                    };
+
+                    scope.define(name, value);
+                }
+
+                self.eval_expr(body, &scope)
+            }
+        }
+    }
+
+    /// Returns the first arm of a `match` whose pattern matches `value` and whose guard holds,
+    /// along with the scope binding the variables of its pattern.
+    fn select_arm<'e>(&mut self, arms: &'e [Arm], value: &Value, env: &Rc<Env>) -> Result<Option<(&'e Arm, Rc<Env>)>, Diagnostic> {
+        for arm in arms {
+            let scope = Env::with_parent(env);
+
+            if !self.match_pattern(&arm.pattern, value.clone(), &scope)? {
+                continue;
+            }
+
+            if let Some(ref guard) = arm.guard {
+                if !located(self.eval_strict(guard, &scope)?.truthy(), guard.span)? {
+                    continue;
+                }
+            }
+
+            return Ok(Some((arm, scope)));
+        }
+
+        Ok(None)
+    }
+
+    /// Returns whether `value` matches `pattern`, binding the variables of the pattern in `scope`.
+    ///
+    /// Values are only forced as far as the pattern needs to inspect them.
+    fn match_pattern(&mut self, pattern: &Pattern, value: Value, scope: &Rc<Env>) -> Result<bool, Diagnostic> {
+        let value = match pattern.kind {
+            PatternKind::Wildcard => return Ok(true),
+
+            PatternKind::Binding(ref name) => {
+                scope.define(name, value);
+
+                return Ok(true);
+            },
+
+            _ => self.force(value, pattern.span)?
+        };
+
+        match (&pattern.kind, &value) {
+            (PatternKind::Bool(expected), Value::Bool(b)) => Ok(expected == b),
+            (PatternKind::Int(expected, _), Value::Number(nb)) => Ok(Number::Int(*expected) == *nb),
+            (PatternKind::Float(expected), Value::Number(nb)) => Ok(Number::F64(*expected) == *nb),
+            (PatternKind::Char(expected), Value::Char(ch)) => Ok(expected == ch),
+            (PatternKind::Str(expected), Value::Str(s)) => Ok(expected == s),
+            (PatternKind::Tuple(patterns), Value::Unit) => Ok(patterns.is_empty()),
+
+            (PatternKind::Tuple(patterns), Value::Tuple(values)) => {
+                if patterns.len() != values.len() {
+                    return Ok(false);
+                }
+
+                self.match_all(patterns.iter().zip(values.iter()), scope)
+            },
+
+            (PatternKind::TupleStruct { name, fields }, _) => {
+                let def = self.pattern_struct(name, pattern.span)?;
+
+                match def.fields {
+                    Fields::Tuple(ref types) if types.len() == fields.len() => (),
+                    _ => return Err(error(format!("Pattern does not match the fields of struct '{}'.", name), pattern.span)
+                        .with_label(def.span, "struct defined here"))
+                }
+
+                match value {
+                    Value::Struct(ref instance) if instance.def.name == *name => {
+                        self.match_all(fields.iter().zip(instance.fields.iter()), scope)
+                    },
+                    _ => Ok(false)
+                }
+            },
+
+            (PatternKind::Struct { name, fields, rest }, _) => {
+                let def = self.pattern_struct(name, pattern.span)?;
+
+                let names = match def.fields {
+                    Fields::Named(ref names) => names,
+                    Fields::Tuple(_) => return Err(error(format!("Struct '{}' has positional fields.", name), pattern.span)
+                        .with_label(def.span, "struct defined here"))
+                };
+
+                if let Some((field, _)) = fields.iter().find(|(field, _)| names.iter().all(|(name, _)| name != field)) {
+                    return Err(error(format!("Struct '{}' has no field '{}'.", name, field), pattern.span)
+                        .with_label(def.span, "struct defined here"));
+                }
+
+                if !rest && fields.len() < names.len() {
+                    return Err(error(format!("Pattern does not mention every field of struct '{}'.", name), pattern.span)
+                        .with_help("add '..' at the end of the pattern to ignore the other fields"));
+                }
+
+                let instance = match value {
+                    Value::Struct(ref instance) if instance.def.name == *name => instance,
+                    _ => return Ok(false)
+                };
+
+                let values = fields.iter()
+                                   .map(|(field, _)| instance.get(&Member::Named(field.clone())).cloned().unwrap_or(Value::Unit))
+                                   .collect::<Vec<_>>();
+
+                self.match_all(fields.iter().map(|(_, pattern)| pattern).zip(values.iter()), scope)
+            },
+
+            _ => Ok(false)
+        }
+    }
+
+    /// Returns whether every value matches its pattern.
+    fn match_all<'p, 'v>(&mut self, pairs: impl Iterator<Item = (&'p Pattern, &'v Value)>, scope: &Rc<Env>) -> Result<bool, Diagnostic> {
+        for (pattern, value) in pairs {
+            if !self.match_pattern(pattern, value.clone(), scope)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Returns the declaration of the struct named by a pattern.
+    fn pattern_struct(&self, name: &str, span: Span) -> Result<Rc<Struct>, Diagnostic> {
+        match self.structs.get(name) {
+            Some(def) => Ok(def.clone()),
+            None => Err(error(format!("Unknown struct '{}'.", name), span))
+        }
+    }
+
+    /// Evaluates the container and the index of a `target[index]` expression, checking
+    /// that the index is within the bounds of a list. The key of a map may be missing.
+    fn eval_index(&mut self, target: &Expr, index: &Expr, env: &Rc<Env>) -> Result<Slot, Diagnostic> {
+        let values = match self.eval_strict(target, env)? {
+            Value::List(values) => values,
+            Value::Map(map) => return Ok(Slot::Entry(map, self.eval_strict(index, env)?)),
+            value => return Err(error(format!("Expected a list or a map but found {}.", value), target.span))
+        };
+
+        let len = values.borrow().len();
+
+        match self.eval_strict(index, env)? {
+            Value::Number(nb) => match nb.as_index() {
+                Some(i) if i < len => Ok(Slot::Element(values, i)),
+                Some(_) => Err(error(format!("Index {} is out of bounds for a list of {} element(s).", nb, len), index.span)
+                    .with_label(target.span, "list indexed here")),
+                None => Err(error(format!("Expected an index but found {}.", nb), index.span)
+                    .with_help("indexes are non-negative integers"))
+            },
+            value => Err(error(format!("Expected an index but found {}.", value), index.span)
+                .with_help("indexes are non-negative integers"))
+        }
+    }
+
+    /// Evaluates an expression whose value is demanded, forcing the resulting thunk if any.
+    fn eval_strict(&mut self, expr: &Expr, env: &Rc<Env>) -> EvalResult {
+        let value = self.eval_expr(expr, env)?;
+
+        self.force(value, expr.span)
+    }
+
+    /// Forces a value demanded at `span` until it is no longer a thunk,
+    /// memoizing the result of every forced thunk.
+    pub fn force(&mut self, mut value: Value, span: Span) -> EvalResult {
+        while let Value::Thunk(thunk) = value {
+            let state = thunk.0.replace(ThunkState::Forcing);
+
+            let result = match state {
+                ThunkState::Call { ref fn_name, ref args, span } => self.call(fn_name, args.clone(), span),
+                ThunkState::Apply { ref callee, ref args, span } => self.call_value(callee.clone(), args.clone(), span),
+                ThunkState::Expr { ref expr, ref env } => self.nested(span, |this| this.eval_expr(expr, env)),
+                ThunkState::Forcing => return Err(error("Thunk depends on its own value.", span)),
+                ThunkState::Forced(ref value) => Ok(value.clone()),
+            };
+
+            value = match result.and_then(|value| self.force(value, span)) {
+                Ok(value) => value,
+                Err(err) => {
+                    // Restore the computation, so that forcing the thunk again retries it.
+                    thunk.0.replace(state);
+
+                    return Err(err);
+                }
+            };
+
+            thunk.0.replace(ThunkState::Forced(value.clone()));
+        }
+
+        Ok(value)
+    }
+
+    /// Applies a built-in binary operator, or a user-defined `binary` function.
+    fn eval_binary(&mut self, op: &str, left: Value, right: Value, span: Span) -> EvalResult {
+        let number = |value: &Value| located(value.as_number(), span);
+        let name = format!("binary{}", op);
+        let user_defined = self.functions.contains_key(&name);
+
+        let value = match (op, &left, &right) {
+            ("==", _, _) => Value::Bool(left == right),
+            ("!=", _, _) => Value::Bool(left != right),
+
+            ("&", Value::Bool(l), Value::Bool(r)) => Value::Bool(*l & *r),
+            ("|", Value::Bool(l), Value::Bool(r)) => Value::Bool(*l | *r),
+            ("^", Value::Bool(l), Value::Bool(r)) => Value::Bool(*l ^ *r),
+
+            ("+", Value::Str(l), Value::Str(r)) => Value::Str(format!("{}{}", l, r)),
+            ("<", Value::Str(l), Value::Str(r)) => Value::Bool(l < r),
+            (">", Value::Str(l), Value::Str(r)) => Value::Bool(l > r),
+            ("<=", Value::Str(l), Value::Str(r)) => Value::Bool(l <= r),
+            (">=", Value::Str(l), Value::Str(r)) => Value::Bool(l >= r),
+
+            ("<", Value::Char(l), Value::Char(r)) => Value::Bool(l < r),
+            (">", Value::Char(l), Value::Char(r)) => Value::Bool(l > r),
+            ("<=", Value::Char(l), Value::Char(r)) => Value::Bool(l <= r),
+            (">=", Value::Char(l), Value::Char(r)) => Value::Bool(l >= r),
+
+            // Numbers of different types are combined by the user-defined operator, if any
+            ("+" | "-" | "*" | "/" | "%", Value::Number(l), Value::Number(r)) if !user_defined || l.is_compatible(*r) => {
+                Value::Number(located(l.binary(op, *r), span)?)
+            },
+            ("<" | ">" | "<=" | ">=", Value::Number(l), Value::Number(r)) if !user_defined || l.is_compatible(*r) => {
+                let ordering = located(l.compare(*r), span)?;
+
+                Value::Bool(ordering.is_some_and(|ordering| match op {
+                    "<" => ordering.is_lt(),
+                    ">" => ordering.is_gt(),
+                    "<=" => ordering.is_le(),
+                    _ => ordering.is_ge()
+                }))
+            },
+
+            _ => {
+                // Builtin operators apply to other values only if they are user-defined for them
+                if matches!(op, "+" | "-" | "*" | "/" | "%" | "<" | ">" | "<=" | ">=") && !user_defined {
+                    number(&left)?;
+                    number(&right)?;
+                }
+
+                return self.call(&name, vec![left, right], span);
+            }
+        };
+
+        Ok(value)
+    }
+
+    /// Calls the function `name`, preferring user-defined functions over
+    /// the constructors of tuple structs, and those over builtins.
+    ///
+    /// Function bodies are eager scopes: a thunk they return is motivated before returning.
+    fn call(&mut self, name: &str, args: Vec<Value>, span: Span) -> EvalResult {
+        let function = match self.functions.get(name) {
+            Some(function) => function.clone(),
+            None => match self.structs.get(name) {
+                Some(def) => return self.construct(def.clone(), args, span),
+                None => return self.call_builtin(name, args, span)
+            }
+        };
+
+        let params = &function.prototype.args;
+
+        if params.len() != args.len() {
+            return Err(error(format!("Function '{}' expects {} argument(s) but {} were given.",
+                                     name, params.len(), args.len()), span)
+                .with_label(function.prototype.span, "function defined here"));
+        }
+
+        let args = params.iter()
+                         .zip(args)
+                         .map(|((_, ty), arg)| located(typed(arg, ty), span))
+                         .collect::<Result<Vec<_>, _>>()?;
+
+        if !self.caches.contains_key(name) {
+            return self.apply(&function, args, span);
+        }
+
+        // Memoized functions are keyed on the values of their arguments, which are thus forced.
+        let args = args.into_iter()
+                       .map(|arg| self.force(arg, span))
+                       .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(value) = self.caches.get_mut(name).and_then(|memo| memo.get(&args)) {
+            return Ok(value);
+        }
+
+        let value = self.apply(&function, args.clone(), span)?;
+
+        if let Some(memo) = self.caches.get_mut(name) {
+            memo.insert(args, value.clone());
+        }
+
+        Ok(value)
+    }
+
+    /// Runs the body of a function whose arity has been checked.
+    fn apply(&mut self, function: &Function, args: Vec<Value>, span: Span) -> EvalResult {
+        match function.body {
+            Some(ref body) => {
+                let scope = Env::with_parent(&self.globals);
+
+                for ((param, _), arg) in function.prototype.args.iter().zip(args) {
+                    scope.define(param, arg);
+                }
+
+                let value = self.nested(span, |this| this.eval_strict(body, &scope))?;
+
+                match function.prototype.ret {
+                    Some(ref ty) => located(typed(value, ty), span),
+                    None => Ok(value)
+                }
+            },
+
+            None => self.call_builtin(&function.prototype.name, args, span)
+        }
+    }
+
+    /// Calls the closure a value evaluates to.
+    ///
+    /// Like function bodies, closure bodies are eager scopes, which run in the scope the closure captured.
+    fn call_value(&mut self, callee: Value, args: Vec<Value>, span: Span) -> EvalResult {
+        let closure = match self.force(callee, span)? {
+            Value::Closure(closure) => closure,
+            value => return Err(error(format!("Expected a closure but found {}.", value), span))
+        };
+
+        if closure.params.len() != args.len() {
+            return Err(error(format!("Closure expects {} argument(s) but {} were given.",
+                                     closure.params.len(), args.len()), span)
+                .with_label(closure.span, "closure defined here"));
+        }
+
+        let scope = Env::with_parent(&closure.env);
+
+        for (param, arg) in closure.params.iter().zip(args) {
+            scope.define(param, arg);
+        }
+
+        self.nested(span, |this| this.eval_strict(&closure.body, &scope))
+    }
+
+    /// Runs a call made at `span` one level deeper, failing instead if it would exceed `MAX_CALL_DEPTH`.
+    fn nested(&mut self, span: Span, call: impl FnOnce(&mut Self) -> EvalResult) -> EvalResult {
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(error(format!("Recursion limit exceeded: more than {} nested calls.", MAX_CALL_DEPTH), span)
+                .with_help("make sure the recursion ends, or force the intermediate results of long chains of thunks"));
+        }
+
+        self.depth += 1;
+
+        let result = call(self);
+
+        self.depth -= 1;
+        result
+    }
+
+    /// Builds an instance of a tuple struct, after forcing its fields and typing the integer literals among them.
+    fn construct(&mut self, def: Rc<Struct>, args: Vec<Value>, span: Span) -> EvalResult {
+        let types = match def.fields {
+            Fields::Tuple(ref types) => types,
+            Fields::Named(_) => return Err(error(format!("Struct '{}' has named fields.", def.name), span)
+                .with_label(def.span, "struct defined here")
+                .with_help(format!("use '{} {{ field: value, ... }}' to build it", def.name)))
+        };
+
+        if types.len() != args.len() {
+            return Err(error(format!("Struct '{}' has {} field(s) but {} were given.",
+                                     def.name, types.len(), args.len()), span)
+                .with_label(def.span, "struct defined here"));
+        }
+
+        let fields = args.into_iter()
+                         .zip(types)
+                         .map(|(arg, ty)| self.force(arg, span).and_then(|value| located(typed(value, ty), span)))
+                         .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Value::Struct(Rc::new(Instance { def, fields })))
+    }
+
+    /// Calls a native function, after forcing its arguments.
+    fn call_builtin(&mut self, name: &str, args: Vec<Value>, span: Span) -> EvalResult {
+        let builtin = match self.builtins.get(name) {
+            Some(builtin) => *builtin,
+            None => return Err(error(format!("Unknown function '{}'.", name), span))
+        };
+
+        let args = args.into_iter()
+                       .map(|arg| self.force(arg, span))
+                       .collect::<Result<Vec<_>, _>>()?;
+
+        located(builtin(&args), span)
+    }
+}
+
+/// Creates a runtime error located at `span`.
+fn error(msg: impl Into<String>, span: Span) -> Diagnostic {
+    Diagnostic::error(msg, span).with_code(RUNTIME_ERROR)
+}
+
+/// Locates the error message of a `Value` operation at `span`.
+fn located<T>(result: Result<T, String>, span: Span) -> Result<T, Diagnostic> {
+    result.map_err(|msg| error(msg, span))
+}
+
+/// Replaces every `{}` in the first argument by the following arguments.
+fn format_args(args: &[Value]) -> Result<String, String> {
+    let (fmt, mut rest) = match args.split_first() {
+        Some((Value::Str(fmt), rest)) => (fmt, rest.iter()),
+        Some((value, _)) => return Ok(value.to_string()),
+        None => return Ok(String::new())
+    };
+
+    let mut out = String::new();
+    let mut pieces = fmt.split("{}").peekable();
+
+    while let Some(piece) = pieces.next() {
+        out.push_str(piece);
+
+        if pieces.peek().is_some() {
+            match rest.next() {
+                Some(value) => out.push_str(&value.to_string()),
+                None => return Err("Missing argument for '{}' in format string.".to_owned())
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Gives an integer literal the numeric type it is declared with, such as the type of a parameter.
+///
+/// Thunks are left as they are, since forcing them early would defeat their laziness.
+fn typed(value: Value, ty: &Type) -> Result<Value, String> {
+    match (value, ty) {
+        (Value::Number(nb @ Number::Int(_)), Type::Named(name)) if NUMERIC_TYPES.contains(&name.as_str()) => {
+            nb.cast(name).map(Value::Number)
+        },
+        (value, Type::Ref(ty)) => typed(value, ty),
+        (value, _) => Ok(value)
+    }
+}
+
+fn number_arg(args: &[Value]) -> Result<Number, String> {
+    match args {
+        [value] => value.as_number(),
+        _ => Err(format!("Expected 1 argument but {} were given.", args.len()))
+    }
+}
+
+fn builtin_print(args: &[Value]) -> Result<Value, String> {
+    print!("{}", format_args(args)?);
+
+    Ok(Value::Unit)
+}
+
+fn builtin_println(args: &[Value]) -> Result<Value, String> {
+    println!("{}", format_args(args)?);
+
+    Ok(Value::Unit)
+}
+
+fn builtin_getline(args: &[Value]) -> Result<Value, String> {
+    if !args.is_empty() {
+        return Err(format!("Expected 0 arguments but {} were given.", args.len()));
+    }
+
+    let mut line = String::new();
+
+    match std::io::stdin().read_line(&mut line) {
+        Ok(0) => Err("Reached the end of the standard input.".to_owned()),
+        Ok(_) => Ok(Value::Str(line.trim_end_matches(&['\r', '\n'][..]).to_owned())),
+        Err(err) => Err(format!("Cannot read the standard input: {}.", err))
+    }
+}
+
+fn builtin_len(args: &[Value]) -> Result<Value, String> {
+    match args {
+        [Value::List(values)] => Ok(Value::Number(Number::U64(values.borrow().len() as u64))),
+        [Value::Map(map)] => Ok(Value::Number(Number::U64(map.borrow().entries().len() as u64))),
+        [Value::Str(s)] => Ok(Value::Number(Number::U64(s.chars().count() as u64))),
+        [value] => Err(format!("Expected a list, a map or a string but found {}.", value)),
+        _ => Err(format!("Expected 1 argument but {} were given.", args.len()))
+    }
+}
+
+fn builtin_push(args: &[Value]) -> Result<Value, String> {
+    match args {
+        [Value::List(values), value] => {
+            values.borrow_mut().push(value.clone());
+
+            Ok(Value::Unit)
+        },
+        [value, _] => Err(format!("Expected a list but found {}.", value)),
+        _ => Err(format!("Expected 2 arguments but {} were given.", args.len()))
+    }
+}
+
+fn builtin_pop(args: &[Value]) -> Result<Value, String> {
+    match args {
+        [Value::List(values)] => values.borrow_mut().pop().ok_or_else(|| "Cannot pop from an empty list.".to_owned()),
+        [value] => Err(format!("Expected a list but found {}.", value)),
+        _ => Err(format!("Expected 1 argument but {} were given.", args.len()))
+    }
+}
+
+fn builtin_contains(args: &[Value]) -> Result<Value, String> {
+    match args {
+        [Value::List(values), value] => Ok(Value::Bool(values.borrow().contains(value))),
+        [Value::Map(map), key] => Ok(Value::Bool(map.borrow().get(key).is_some())),
+        [Value::Str(s), Value::Str(sub)] => Ok(Value::Bool(s.contains(sub.as_str()))),
+        [Value::Str(s), Value::Char(ch)] => Ok(Value::Bool(s.contains(*ch))),
+        [value, _] => Err(format!("Expected a list, a map or a string but found {}.", value)),
+        _ => Err(format!("Expected 2 arguments but {} were given.", args.len()))
+    }
+}
+
+fn builtin_insert(args: &[Value]) -> Result<Value, String> {
+    match args {
+        [Value::Map(map), key, value] => {
+            map.borrow_mut().insert(key.clone(), value.clone());
+
+            Ok(Value::Unit)
+        },
+        [value, _, _] => Err(format!("Expected a map but found {}.", value)),
+        _ => Err(format!("Expected 3 arguments but {} were given.", args.len()))
+    }
+}
+
+fn builtin_remove(args: &[Value]) -> Result<Value, String> {
+    match args {
+        [Value::Map(map), key] => map.borrow_mut().remove(key).ok_or_else(|| format!("Key {} is not in the map.", key)),
+        [value, _] => Err(format!("Expected a map but found {}.", value)),
+        _ => Err(format!("Expected 2 arguments but {} were given.", args.len()))
+    }
+}
+
+/// Returns a list built from the entries of a map, in insertion order.
+fn map_list(args: &[Value], entry: fn(&(Value, Value)) -> Value) -> Result<Value, String> {
+    match args {
+        [Value::Map(map)] => Ok(Value::List(Rc::new(RefCell::new(map.borrow().entries().iter().map(entry).collect())))),
+        [value] => Err(format!("Expected a map but found {}.", value)),
+        _ => Err(format!("Expected 1 argument but {} were given.", args.len()))
+    }
+}
+
+fn builtin_keys(args: &[Value]) -> Result<Value, String> {
+    map_list(args, |(key, _)| key.clone())
+}
+
+fn builtin_values(args: &[Value]) -> Result<Value, String> {
+    map_list(args, |(_, value)| value.clone())
+}
+
+fn builtin_entries(args: &[Value]) -> Result<Value, String> {
+    map_list(args, |(key, value)| Value::Tuple(Rc::new(vec![key.clone(), value.clone()])))
+}
+
+fn builtin_neg(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Number(number_arg(args)?.neg()?))
+}
+
+fn builtin_not(args: &[Value]) -> Result<Value, String> {
+    match args {
+        [Value::Bool(b)] => Ok(Value::Bool(!b)),
+        _ => Ok(Value::Bool(number_arg(args)?.is_zero()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Parser;
+    use std::thread;
+
+    /// Loads a program and runs its `main` function on a stack as large as the interpreter's,
+    /// returning the message of the first error if any.
+    fn run(source: &str) -> Result<(), String> {
+        let source = source.to_owned();
+        let run = move || {
+            let mut evaluator = Evaluator::new();
+            let program = Parser::new(source).parse_program().map_err(|err| err.message)?;
+
+            if let Some(err) = evaluator.load(program).into_iter().find(Diagnostic::is_error) {
+                return Err(err.message);
+            }
+
+            match evaluator.run("main") {
+                Some(result) => result.map(|_| ()).map_err(|err| err.message),
+                None => Err("no 'main' function".to_owned())
+            }
+        };
+
+        thread::Builder::new().stack_size(STACK_SIZE).spawn(run).unwrap().join().unwrap()
+    }
+
+    #[test]
+    fn deep_recursion_is_an_error() {
+        let source = "fn down(n: u64) -> u64 { if n == 0 then 0 else down!(n - 1) }";
+
+        assert_eq!(run(&format!("{} fn main() {{ down!({}) }}", source, MAX_CALL_DEPTH - 10)), Ok(()));
+        assert_eq!(run(&format!("{} fn main() {{ down!({}) }}", source, MAX_CALL_DEPTH)),
+                   Err(format!("Recursion limit exceeded: more than {} nested calls.", MAX_CALL_DEPTH)));
+    }
+
+    #[test]
+    fn user_defined_operators_combine_numbers_of_different_types() {
+        let source = "fn binary + 20 (a: u64, b: f64) -> f64 { f64!(a) + b }
+                      fn main() { if 1u64 + 2.5 == 3.5 && 1u64 + 2u64 == 3u64 then () else [()][1] }";
+
+        assert_eq!(run(source), Ok(()));
+        assert_eq!(run("fn main() { 1u64 + 2.5 }"), Err("Operator '+' cannot be applied to 'u64' and 'f64'.".to_owned()));
+    }
+
+    #[test]
+    fn tuples_of_thunks_compare_by_value() {
+        let source = "fn f(x: u64) -> u64 { x + 1 }
+                      fn main() { if (f(1), 2) == (f(1), 2) && (f(1), 2) != (f(2), 2) then () else [()][1] }";
+
+        assert_eq!(run(source), Ok(()));
+    }
+
+    #[test]
+    fn calls_to_native_functions_are_checked() {
+        assert_eq!(run("fn main() { nosuch!(1) }"), Err("Unknown function 'nosuch'.".to_owned()));
+        assert_eq!(run("fn main() { len!([1], 2) }"), Err("Function 'len' expects 1 argument(s) but 2 were given.".to_owned()));
+        assert_eq!(run("fn main() { sqrt!(\"a\") }"), Err("Function 'sqrt' cannot be applied to 'str'.".to_owned()));
+        assert_eq!(run("fn main() { getline!() }"), Err("Unknown function 'getline'.".to_owned()));
+        assert_eq!(run("extern sqrt(x: f64) -> f64; fn main() { let f = |xs| pop!(xs); sqrt!(f!([2.0])) }"), Ok(()));
+    }
+}
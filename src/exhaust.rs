@@ -0,0 +1,556 @@
+use crate::diag::{Diagnostic, INVALID_PATTERN, NON_EXHAUSTIVE_MATCH, UNREACHABLE_ARM};
+use crate::lex::Span;
+use crate::parse::{Arm, Expr, ExprKind, Fields, Function, Pattern, PatternKind, Stmt, Struct};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Defines a constructor of values, as far as patterns can tell them apart.
+#[derive(Debug, Clone, PartialEq)]
+enum Ctor {
+    Bool(bool),
+    Int(i128),
+    /// A float, compared by its bits (with `-0` normalized to `0`).
+    Float(u64),
+    Char(char),
+    Str(String),
+    /// A tuple of the given length; `()` is the empty tuple.
+    Tuple(usize),
+    /// An instance of the named struct, whose fields are given in declaration order.
+    Struct(String),
+}
+
+/// Defines a pattern, reduced to what matters to exhaustiveness.
+#[derive(Debug, Clone, PartialEq)]
+enum Pat {
+    Any,
+    Ctor(Ctor, Vec<Pat>),
+}
+
+/// Checks the `match` expressions of a function, reporting the matches that
+/// are not exhaustive as errors and the arms that cannot be reached as warnings.
+pub fn check_function(function: &Function, structs: &HashMap<&str, &Struct>) -> Vec<Diagnostic> {
+    let mut checker = Checker { structs, diagnostics: vec![] };
+
+    if let Some(ref body) = function.body {
+        checker.visit(body);
+    }
+
+    checker.diagnostics
+}
+
+/// Represents the exhaustiveness checker, along with the diagnostics it reported.
+struct Checker<'a> {
+    structs: &'a HashMap<&'a str, &'a Struct>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    /// Checks every `match` nested in an expression.
+    fn visit(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Bool(_) | ExprKind::Int(..) | ExprKind::Float(_) | ExprKind::Char(_) | ExprKind::Str(_) | ExprKind::Variable(_) => (),
+
+            ExprKind::Binary { left, right, .. } => {
+                self.visit(left);
+                self.visit(right);
+            },
+
+            ExprKind::Block { stmts, tail } => {
+                for stmt in stmts {
+                    match stmt {
+                        Stmt::Let { value, .. } => self.visit(value),
+                        Stmt::Expr(expr) => self.visit(expr),
+                    }
+                }
+
+                if let Some(tail) = tail {
+                    self.visit(tail);
+                }
+            },
+
+            ExprKind::Call { args, .. } | ExprKind::Tuple(args) | ExprKind::List(args) => {
+                for arg in args {
+                    self.visit(arg);
+                }
+            },
+
+            ExprKind::Conditional { cond, consequence, alternative } => {
+                self.visit(cond);
+                self.visit(consequence);
+                self.visit(alternative);
+            },
+
+            ExprKind::Field { expr, .. } | ExprKind::Mot(expr) => self.visit(expr),
+
+            ExprKind::For { start, end, step, body, .. } => {
+                self.visit(start);
+                self.visit(end);
+
+                if let Some(step) = step {
+                    self.visit(step);
+                }
+
+                self.visit(body);
+            },
+
+            ExprKind::Apply { callee, args, .. } => {
+                self.visit(callee);
+
+                for arg in args {
+                    self.visit(arg);
+                }
+            },
+
+            ExprKind::Closure { body, .. } => self.visit(body),
+
+            ExprKind::MethodCall { receiver, args, .. } => {
+                self.visit(receiver);
+
+                for arg in args {
+                    self.visit(arg);
+                }
+            },
+
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.visit(key);
+                    self.visit(value);
+                }
+            },
+
+            ExprKind::Index { expr, index } => {
+                self.visit(expr);
+                self.visit(index);
+            },
+
+            ExprKind::Lazy(expr) => self.visit(expr),
+
+            ExprKind::Match { expr: scrutinee, arms } => {
+                self.visit(scrutinee);
+
+                for arm in arms {
+                    if let Some(ref guard) = arm.guard {
+                        self.visit(guard);
+                    }
+
+                    self.visit(&arm.body);
+                }
+
+                self.check_match(Span { end: scrutinee.span.end, ..expr.span }, arms);
+            },
+
+            ExprKind::StructLit { fields, .. } => {
+                for (_, value) in fields {
+                    self.visit(value);
+                }
+            },
+
+            ExprKind::VarIn { variables, body } => {
+                for (_, initializer) in variables {
+                    if let Some(initializer) = initializer {
+                        self.visit(initializer);
+                    }
+                }
+
+                self.visit(body);
+            },
+        }
+    }
+
+    /// Checks that the arms of a `match` are exhaustive and reachable,
+    /// given the span of its head ('match expr').
+    fn check_match(&mut self, span: Span, arms: &[Arm]) {
+        let mut rows: Vec<(&Arm, Vec<Pat>)> = vec![];
+
+        for arm in arms {
+            let pat = match self.lower(&arm.pattern) {
+                Ok(pat) => pat,
+                Err(err) => return self.diagnostics.push(err)
+            };
+
+            let previous = rows.iter().map(|(_, row)| row.clone()).collect::<Vec<_>>();
+
+            let v = std::slice::from_ref(&pat);
+
+            if useful(&previous, v).is_none() {
+                let mut warning = Diagnostic::warning("Unreachable match arm.", arm.pattern.span)
+                    .with_code(UNREACHABLE_ARM)
+                    .with_note("every value it matches is matched by a previous arm");
+
+                // Point at the previous arm that covers it on its own, if any
+                if let Some((previous, _)) = rows.iter().find(|(_, row)| useful(std::slice::from_ref(row), v).is_none()) {
+                    warning = warning.with_label(previous.pattern.span, "first matched here");
+                }
+
+                self.diagnostics.push(warning);
+            }
+
+            // Guarded arms may not match, so they do not cover any value
+            if arm.guard.is_none() {
+                rows.push((arm, vec![pat]));
+            }
+        }
+
+        let rows = rows.into_iter().map(|(_, row)| row).collect::<Vec<_>>();
+
+        if let Some(witness) = useful(&rows, &[Pat::Any]) {
+            let missing = Display { pat: &witness[0], structs: self.structs }.to_string();
+
+            let help = match witness[0] {
+                Pat::Any => "add a '_' arm that matches any value".to_owned(),
+                _ => format!("add an arm for '{}', or a '_' arm that matches any value", missing)
+            };
+
+            self.diagnostics.push(Diagnostic::error(format!("Non-exhaustive match: pattern '{}' is not covered.", missing), span)
+                .with_code(NON_EXHAUSTIVE_MATCH)
+                .with_help(help));
+        }
+    }
+
+    /// Reduces a pattern to a `Pat`, giving the fields of structs in declaration order.
+    fn lower(&self, pattern: &Pattern) -> Result<Pat, Diagnostic> {
+        let pat = match &pattern.kind {
+            PatternKind::Wildcard | PatternKind::Binding(_) => Pat::Any,
+            PatternKind::Bool(b) => Pat::Ctor(Ctor::Bool(*b), vec![]),
+            PatternKind::Int(nb, _) => Pat::Ctor(Ctor::Int(*nb), vec![]),
+            PatternKind::Float(nb) => Pat::Ctor(Ctor::Float(if *nb == 0.0 { 0 } else { nb.to_bits() }), vec![]),
+            PatternKind::Char(ch) => Pat::Ctor(Ctor::Char(*ch), vec![]),
+            PatternKind::Str(s) => Pat::Ctor(Ctor::Str(s.clone()), vec![]),
+
+            PatternKind::Tuple(patterns) => {
+                let pats = patterns.iter().map(|pattern| self.lower(pattern)).collect::<Result<Vec<_>, _>>()?;
+
+                Pat::Ctor(Ctor::Tuple(pats.len()), pats)
+            },
+
+            PatternKind::TupleStruct { name, fields } => {
+                let def = self.find_struct(name, pattern)?;
+
+                match def.fields {
+                    Fields::Tuple(ref types) if types.len() == fields.len() => (),
+                    _ => return Err(struct_error(format!("Pattern does not match the fields of struct '{}'.", name), pattern, def))
+                }
+
+                let pats = fields.iter().map(|pattern| self.lower(pattern)).collect::<Result<Vec<_>, _>>()?;
+
+                Pat::Ctor(Ctor::Struct(name.clone()), pats)
+            },
+
+            PatternKind::Struct { name, fields, rest } => {
+                let def = self.find_struct(name, pattern)?;
+
+                let names = match def.fields {
+                    Fields::Named(ref names) => names,
+                    Fields::Tuple(_) => return Err(struct_error(format!("Struct '{}' has positional fields.", name), pattern, def))
+                };
+
+                if let Some((field, _)) = fields.iter().find(|(field, _)| names.iter().all(|(name, _)| name != field)) {
+                    return Err(struct_error(format!("Struct '{}' has no field '{}'.", name, field), pattern, def));
+                }
+
+                if !rest && fields.len() < names.len() {
+                    return Err(struct_error(format!("Pattern does not mention every field of struct '{}'.", name), pattern, def)
+                        .with_help("add '..' at the end of the pattern to ignore the other fields"));
+                }
+
+                let mut pats = vec![];
+
+                for (name, _) in names {
+                    pats.push(match fields.iter().find(|(field, _)| field == name) {
+                        Some((_, pattern)) => self.lower(pattern)?,
+                        None => Pat::Any
+                    });
+                }
+
+                Pat::Ctor(Ctor::Struct(name.clone()), pats)
+            },
+        };
+
+        Ok(pat)
+    }
+
+    /// Returns the declaration of the struct named by a pattern.
+    fn find_struct(&self, name: &str, pattern: &Pattern) -> Result<&'a Struct, Diagnostic> {
+        match self.structs.get(name) {
+            Some(def) => Ok(*def),
+            None => Err(Diagnostic::error(format!("Unknown struct '{}'.", name), pattern.span).with_code(INVALID_PATTERN))
+        }
+    }
+}
+
+/// Creates an error about a pattern that does not fit the struct `def`.
+fn struct_error(msg: String, pattern: &Pattern, def: &Struct) -> Diagnostic {
+    Diagnostic::error(msg, pattern.span)
+        .with_code(INVALID_PATTERN)
+        .with_label(def.span, "struct defined here")
+}
+
+/// Returns the number of fields of the values built by a constructor.
+fn arity(ctor: &Ctor, rows: &[Vec<Pat>]) -> usize {
+    match ctor {
+        Ctor::Bool(_) | Ctor::Int(_) | Ctor::Float(_) | Ctor::Char(_) | Ctor::Str(_) => 0,
+        Ctor::Tuple(len) => *len,
+
+        // The lowering checked that every pattern of a struct has all of its fields
+        Ctor::Struct(_) => rows.iter()
+                               .find_map(|row| match row[0] {
+                                   Pat::Ctor(ref c, ref args) if c == ctor => Some(args.len()),
+                                   _ => None
+                               })
+                               .unwrap_or(0)
+    }
+}
+
+/// Returns the rows matching the constructor `ctor` in their first column, with
+/// the first column replaced by the fields of the constructor.
+fn specialize(rows: &[Vec<Pat>], ctor: &Ctor, arity: usize) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter_map(|row| {
+            let fields = match row[0] {
+                Pat::Any => vec![Pat::Any; arity],
+                Pat::Ctor(ref c, ref args) if c == ctor => args.clone(),
+                Pat::Ctor(..) => return None
+            };
+
+            Some(fields.into_iter().chain(row[1..].iter().cloned()).collect())
+        })
+        .collect()
+}
+
+/// Returns the rows whose first column matches any value, without that column.
+fn default_rows(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter(|row| row[0] == Pat::Any)
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+/// Returns the constructors that cover every value they can be confused with,
+/// given the constructors found in a column, or `None` if some values remain.
+///
+/// Columns that mix different kinds of values are never complete.
+fn complete_signature(ctors: &[Ctor]) -> Option<Vec<Ctor>> {
+    match ctors.first()? {
+        Ctor::Bool(_) if ctors.iter().all(|c| matches!(c, Ctor::Bool(_))) => {
+            let both = vec![Ctor::Bool(false), Ctor::Bool(true)];
+
+            if both.iter().all(|b| ctors.contains(b)) { Some(both) } else { None }
+        },
+
+        Ctor::Tuple(_) | Ctor::Struct(_) if ctors.iter().all(|c| c == &ctors[0]) => Some(vec![ctors[0].clone()]),
+
+        _ => None
+    }
+}
+
+/// Returns a value matched by the patterns `v` but by none of the rows, if any.
+///
+/// This is the usefulness algorithm described by Luc Maranget in
+/// "Warnings for pattern matching", which finds both missing and redundant patterns.
+fn useful(rows: &[Vec<Pat>], v: &[Pat]) -> Option<Vec<Pat>> {
+    let (head, rest) = match v.split_first() {
+        Some(split) => split,
+        None => return if rows.is_empty() { Some(vec![]) } else { None }
+    };
+
+    match head {
+        Pat::Ctor(ctor, args) => {
+            let specialized = specialize(rows, ctor, args.len());
+            let v = args.iter().chain(rest).cloned().collect::<Vec<_>>();
+
+            useful(&specialized, &v).map(|witness| rebuild(ctor, args.len(), witness))
+        },
+
+        Pat::Any => {
+            let mut ctors: Vec<Ctor> = vec![];
+
+            for row in rows {
+                if let Pat::Ctor(ref ctor, _) = row[0] {
+                    if !ctors.contains(ctor) {
+                        ctors.push(ctor.clone());
+                    }
+                }
+            }
+
+            if let Some(signature) = complete_signature(&ctors) {
+                return signature.iter().find_map(|ctor| {
+                    let arity = arity(ctor, rows);
+                    let specialized = specialize(rows, ctor, arity);
+                    let v = vec![Pat::Any; arity].into_iter().chain(rest.iter().cloned()).collect::<Vec<_>>();
+
+                    useful(&specialized, &v).map(|witness| rebuild(ctor, arity, witness))
+                });
+            }
+
+            let witness = useful(&default_rows(rows), rest)?;
+
+            // Name the missing boolean, rather than any value
+            let missing = match ctors.as_slice() {
+                [Ctor::Bool(b)] => Pat::Ctor(Ctor::Bool(!b), vec![]),
+                _ => Pat::Any
+            };
+
+            Some(std::iter::once(missing).chain(witness).collect())
+        }
+    }
+}
+
+/// Rebuilds a witness whose first `arity` patterns are the fields of `ctor`.
+fn rebuild(ctor: &Ctor, arity: usize, mut witness: Vec<Pat>) -> Vec<Pat> {
+    let rest = witness.split_off(arity);
+
+    std::iter::once(Pat::Ctor(ctor.clone(), witness)).chain(rest).collect()
+}
+
+/// Formats a `Pat` like the pattern it was lowered from.
+struct Display<'a> {
+    pat: &'a Pat,
+    structs: &'a HashMap<&'a str, &'a Struct>,
+}
+
+impl<'a> Display<'a> {
+    fn with<'b>(&'b self, pat: &'b Pat) -> Display<'b> {
+        Display { pat, structs: self.structs }
+    }
+}
+
+impl<'a> fmt::Display for Display<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (ctor, args) = match self.pat {
+            Pat::Any => return write!(f, "_"),
+            Pat::Ctor(ctor, args) => (ctor, args)
+        };
+
+        let list = |f: &mut fmt::Formatter| -> fmt::Result {
+            for (i, arg) in args.iter().enumerate() {
+                write!(f, "{}{}", if i == 0 { "" } else { ", " }, self.with(arg))?;
+            }
+
+            Ok(())
+        };
+
+        match ctor {
+            Ctor::Bool(b) => write!(f, "{}", b),
+            Ctor::Int(nb) => write!(f, "{}", nb),
+            Ctor::Float(bits) => write!(f, "{}", f64::from_bits(*bits)),
+            Ctor::Char(ch) => write!(f, "{:?}", ch),
+            Ctor::Str(s) => write!(f, "{:?}", s),
+
+            Ctor::Tuple(len) => {
+                write!(f, "(")?;
+                list(f)?;
+                write!(f, "{})", if *len == 1 { "," } else { "" })
+            },
+
+            Ctor::Struct(name) => match self.structs.get(name.as_str()).map(|def| &def.fields) {
+                Some(Fields::Named(names)) => {
+                    write!(f, "{} {{", name)?;
+
+                    for (i, ((field, _), arg)) in names.iter().zip(args).enumerate() {
+                        write!(f, "{} {}: {}", if i == 0 { "" } else { "," }, field, self.with(arg))?;
+                    }
+
+                    write!(f, " }}")
+                },
+
+                _ => {
+                    write!(f, "{}(", name)?;
+                    list(f)?;
+                    write!(f, ")")
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diag::Severity;
+    use crate::parse::{Item, Parser};
+
+    /// Checks the functions of a program, returning the severity and message of every diagnostic.
+    fn check(source: &str) -> Vec<(Severity, String)> {
+        let program = Parser::new(source.to_owned()).parse_program().unwrap();
+
+        let structs = program.items.iter()
+                                   .filter_map(|item| match item {
+                                       Item::Struct(def) => Some((def.name.as_str(), def)),
+                                       _ => None
+                                   })
+                                   .collect::<HashMap<_, _>>();
+
+        program.items.iter()
+                     .filter_map(|item| match item {
+                         Item::Function(function) => Some(check_function(function, &structs)),
+                         _ => None
+                     })
+                     .flatten()
+                     .map(|diagnostic| (diagnostic.severity, diagnostic.message))
+                     .collect()
+    }
+
+    fn missing(pattern: &str) -> (Severity, String) {
+        (Severity::Error, format!("Non-exhaustive match: pattern '{}' is not covered.", pattern))
+    }
+
+    fn unreachable() -> (Severity, String) {
+        (Severity::Warning, "Unreachable match arm.".to_owned())
+    }
+
+    #[test]
+    fn exhaustive_matches_are_accepted() {
+        assert_eq!(check("fn f(b: bool) -> u64 { match b { true => 1, false => 0 } }"), vec![]);
+        assert_eq!(check("fn f(n: u64) -> u64 { match n { 0 => 1, _ => n } }"), vec![]);
+        assert_eq!(check("fn f(p: (bool, bool)) -> u64 { match p { (true, _) => 1, (_, true) => 2, (false, false) => 3 } }"), vec![]);
+    }
+
+    #[test]
+    fn missing_booleans_are_named() {
+        assert_eq!(check("fn f(b: bool) -> u64 { match b { true => 1 } }"), vec![missing("false")]);
+        assert_eq!(check("fn f(p: (bool, bool)) -> u64 { match p { (true, _) => 1, (_, true) => 2 } }"),
+                   vec![missing("(false, false)")]);
+    }
+
+    #[test]
+    fn infinite_types_need_a_wildcard() {
+        assert_eq!(check("fn f(n: u64) -> u64 { match n { 0 => 1, 1 => 2 } }"), vec![missing("_")]);
+        assert_eq!(check("fn f(s: str) -> u64 { match s { \"a\" => 1 } }"), vec![missing("_")]);
+    }
+
+    #[test]
+    fn guarded_arms_do_not_cover_values() {
+        assert_eq!(check("fn f(b: bool) -> u64 { match b { true => 1, false if b => 0 } }"), vec![missing("false")]);
+    }
+
+    #[test]
+    fn missing_struct_fields_are_named() {
+        let source = "struct P { x: bool, y: bool }
+                      fn f(p: P) -> u64 { match p { P { x: true, .. } => 1, P { y: true, .. } => 2 } }";
+
+        assert_eq!(check(source), vec![missing("P { x: false, y: false }")]);
+
+        let source = "struct Cell(bool);
+                      fn f(c: Cell) -> u64 { match c { Cell(true) => 1 } }";
+
+        assert_eq!(check(source), vec![missing("Cell(false)")]);
+    }
+
+    #[test]
+    fn arms_after_a_wildcard_are_unreachable() {
+        assert_eq!(check("fn f(n: u64) -> u64 { match n { _ => 1, 0 => 2 } }"), vec![unreachable()]);
+    }
+
+    #[test]
+    fn arms_covered_by_several_arms_are_unreachable() {
+        let source = "fn f(p: (bool, bool)) -> u64 { match p { (true, _) => 1, (false, _) => 2, (_, true) => 3 } }";
+
+        assert_eq!(check(source), vec![unreachable()]);
+    }
+
+    #[test]
+    fn duplicate_literals_are_unreachable() {
+        assert_eq!(check("fn f(c: char) -> u64 { match c { 'a' => 1, 'a' => 2, _ => 3 } }"), vec![unreachable()]);
+        assert_eq!(check("fn f(x: f64) -> u64 { match x { 0.0 => 1, -0.0 => 2, _ => 3 } }"), vec![unreachable()]);
+    }
+}
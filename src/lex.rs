@@ -1,29 +1,48 @@
+use crate::diag::{Diagnostic, LEX_ERROR};
+use crate::num::NUMERIC_TYPES;
+use std::fmt;
 use std::str::Chars;
 use std::iter::Peekable;
 use std::ops::DerefMut;
 
 /// Represents a primitive syntax token.
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
+    Arrow,
     Binary,
     Bang,
+    Bool(bool),
+    Char(char),
     Comma,
     Colon,
     Comment,
+    Dot,
     Fn,
     Else,
     EOF,
+    Ext,
+    FatArrow,
     Extern,
     For,
+    Float(f64),
     Ident(String),
     If,
     In,
-    Number(f64),
-    Op(char),
+    /// An integer literal, along with its type suffix if any, such as `20u8`.
+    Int(u64, Option<String>),
+    Op(String),
     LBrace,
+    LBracket,
+    Let,
+    Match,
     RBrace,
+    RBracket,
     LParen,
+    PathSep,
     RParen,
+    SemiColon,
+    Struct,
     Then,
     Mot,
     Lru,
@@ -35,38 +54,96 @@ pub enum Token {
     Str(String),
 }
 
-/// Defines an error encountered by the `Lexer`.
-pub struct LexError {
-    pub error: &'static str,
-    pub index: usize
+/// Defines a region of the source code, as a range of byte offsets
+/// along with the (1-based) line and column at which it starts.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize
 }
 
-impl LexError {
-    pub fn new(msg: &'static str) -> LexError {
-        LexError { error: msg, index: 0 }
+impl Span {
+    /// Returns the smallest span covering both `self` and `other`, which must come after it.
+    pub fn to(self, other: Span) -> Span {
+        Span { end: other.end.max(self.end), ..self }
     }
+}
 
-    pub fn with_index(msg: &'static str, index: usize) -> LexError {
-        LexError { error: msg, index: index }
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
     }
 }
 
+/// Associates a value, such as a `Token`, with its location in the source code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span
+}
+
 /// Defines the result of a lexing operation; namely a
-/// `Token` on success, or a `LexError` on failure.
-pub type LexResult = Result<Token, LexError>;
+/// spanned `Token` on success, or a `Diagnostic` on failure.
+pub type LexResult = Result<Spanned<Token>, Diagnostic>;
 
 /// Defines a lexer which transforms an input `String` into
 /// a `Token` stream.
 pub struct Lexer<'a> {
     input: &'a str,
     chars: Box<Peekable<Chars<'a>>>,
-    pos: usize
+    pos: usize,
+    last_end: usize,
+    /// Whether the last token can be followed by a field access, such as an identifier or a ')'.
+    has_fields: bool,
+    /// Whether the last token is a '.' that accesses a field of the token before it.
+    field_dot: bool,
+    line_starts: Vec<usize>,
+    offset: usize,
+    first_line: usize
 }
 
 impl<'a> Lexer<'a> {
     /// Creates a new `Lexer`, given its source `input`.
     pub fn new(input: &'a str) -> Lexer<'a> {
-        Lexer { input: input, chars: Box::new(input.chars().peekable()), pos: 0 }
+        Lexer::with_origin(input, 0, 0)
+    }
+
+    /// Creates a new `Lexer` for an `input` that begins at the byte `offset`
+    /// of the (0-based) line `first_line` of a larger source, such as a REPL session.
+    pub fn with_origin(input: &'a str, offset: usize, first_line: usize) -> Lexer<'a> {
+        let line_starts = std::iter::once(0)
+            .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Lexer {
+            input,
+            chars: Box::new(input.chars().peekable()),
+            pos: 0,
+            last_end: 0,
+            has_fields: false,
+            field_dot: false,
+            line_starts,
+            offset,
+            first_line
+        }
+    }
+
+    /// Returns the span of the bytes between `start` and `end` of the input.
+    fn span(&self, start: usize, end: usize) -> Span {
+        let line = match self.line_starts.binary_search(&start) {
+            Ok(line) => line,
+            Err(next) => next - 1
+        };
+        let col = self.input[self.line_starts[line]..start].chars().count() + 1;
+
+        Span { start: start + self.offset, end: end + self.offset, line: line + self.first_line + 1, col }
+    }
+
+    /// Returns an error located between `start` and `end`.
+    fn error(&self, msg: &str, start: usize, end: usize) -> Diagnostic {
+        Diagnostic::error(msg, self.span(start, end)).with_code(LEX_ERROR)
     }
 
     /// Lexes and returns the next `Token` from the source code.
@@ -87,7 +164,7 @@ impl<'a> Lexer<'a> {
                 if ch.is_none() {
                     self.pos = pos;
 
-                    return Ok(Token::EOF);
+                    return Ok(self.eof());
                 }
 
                 if !ch.unwrap().is_whitespace() {
@@ -95,59 +172,126 @@ impl<'a> Lexer<'a> {
                 }
             }
 
-            chars.next();
-            pos += 1;
+            pos += chars.next().map_or(0, char::len_utf8);
         }
 
         let start = pos;
-        let next = chars.next();
+        let next = match chars.next() {
+            Some(ch) => ch,
+            None => return Ok(self.eof())
+        };
 
-        if next.is_none() {
-            return Ok(Token::EOF);
-        }
+        pos += next.len_utf8();
+
+        // Multi-character punctuation and operators take precedence over their first character.
+        let pair = chars.peek().and_then(|&second| match (next, second) {
+            ('=', '>') => Some(Token::FatArrow),
+            ('-', '>') => Some(Token::Arrow),
+            (':', ':') => Some(Token::PathSep),
+            ('&', '&') | ('|', '|') | ('=', '=') | ('!', '=') | ('<', '=') | ('>', '=') => {
+                Some(Token::Op(format!("{}{}", next, second)))
+            },
+            _ => None
+        });
 
-        pos += 1;
+        if let Some(token) = pair {
+            chars.next();
+            pos += 1;
+
+            self.pos = pos;
+            self.last_end = pos;
+            self.has_fields = false;
+            self.field_dot = false;
+
+            return Ok(Spanned { node: token, span: self.span(start, pos) });
+        }
 
         // Actually get the next token.
-        let result = match next.unwrap() {
+        let result = match next {
             '(' => Ok(Token::LParen),
             ')' => Ok(Token::RParen),
             '{' => Ok(Token::LBrace),
             '}' => Ok(Token::RBrace),
+            '[' => Ok(Token::LBracket),
+            ']' => Ok(Token::RBracket),
             ',' => Ok(Token::Comma),
             '!' => Ok(Token::Bang),
             ':' => Ok(Token::Colon),
+            ';' => Ok(Token::SemiColon),
+            '.' => Ok(Token::Dot),
             '"' => {
                 let mut value = String::new();
 
-                while let Ok(ch) = self.read_escaped_char() {
-                    if ch != '"' {
-                        value.push(ch);
-                    } else if ch == '"' {
-                        return Ok(Token::Str(value));
+                // 'read_escaped_char' keeps 'self.pos' up to date while reading the string.
+                self.pos = pos;
+
+                let result = loop {
+                    match self.read_escaped_char(start, '"') {
+                        Ok(None) => break Ok(Token::Str(value)),
+                        Ok(Some(ch)) => value.push(ch),
+                        Err(err) => break Err(err)
                     }
+                };
+
+                pos = self.pos;
+                result
+            },
+
+            '\'' => {
+                self.pos = pos;
+
+                let result = match self.read_escaped_char(start, '\'') {
+                    Ok(None) => Err(self.error("Empty character literal.", start, self.pos)
+                        .with_help("write a character between the quotes, like in 'a'")),
+                    Ok(Some(ch)) if self.chars.peek() == Some(&'\'') => {
+                        self.chars.next();
+                        self.pos += 1;
+
+                        Ok(Token::Char(ch))
+                    },
+                    Ok(Some(_)) if matches!(self.chars.peek(), None | Some('\n')) => Err(self.unclosed_literal(start, '\'')),
+                    Ok(Some(_)) => Err(self.error("Character literal must contain exactly one character.", start, self.pos)
+                        .with_help("use double quotes for a string, like in \"ab\"")),
+                    Err(err) => Err(err)
+                };
+
+                pos = self.pos;
+                result
+            },
+
+            // Raw strings, like 'r"C:\path"' or 'r#"say "hi""#', do not process escapes
+            'r' if src[pos..].trim_start_matches('#').starts_with('"') => {
+                let hashes = src[pos..].len() - src[pos..].trim_start_matches('#').len();
+                let content = pos + hashes + 1;
+                let terminator = format!("\"{}", "#".repeat(hashes));
+
+                let closing = src[content..].find(&terminator);
+                let end = closing.map_or(src.len(), |len| content + len + terminator.len());
+
+                // Skip the characters of the literal
+                while pos < end {
+                    pos += self.chars.next().map_or(1, char::len_utf8);
+                }
+
+                match closing {
+                    Some(len) => Ok(Token::Str(src[content..content + len].to_owned())),
+                    None => Err(self.error("Unclosed raw string literal.", start, content)
+                        .with_help(format!("add '{}' at the end of the string", terminator)))
                 }
-                Err(LexError::new("unclosed string"))
             },
 
-            '.' | '0' ..= '9' => {
-                // Parse number literal
-                loop {
-                    let ch = match chars.peek() {
-                        Some(ch) => *ch,
-                        None => return Ok(Token::EOF)
-                    };
+            '0' ..= '9' => {
+                // Positional fields directly follow the dot after a value, and 'x.0.1' accesses two fields
+                let is_field = self.field_dot && src[..start].ends_with('.');
 
-                    // Parse float.
-                    if ch != '.' && !ch.is_digit(16) {
-                        break;
-                    }
+                let (end, result) = self.lex_number(start, is_field);
 
-                    chars.next();
-                    pos += 1;
+                // Skip the characters of the literal
+                while pos < end {
+                    pos += self.chars.next().map_or(1, char::len_utf8);
                 }
 
-                Ok(Token::Number(src[start..pos].parse().unwrap()))
+                result
             },
 
 
@@ -156,49 +300,58 @@ impl<'a> Lexer<'a> {
                 if let Some('/') = chars.peek() {
                     loop {
                         let ch = chars.next();
-                        pos += 1;
-                        if ch == Some('\n') {
+                        pos += ch.map_or(0, char::len_utf8);
+                        if ch == Some('\n') || ch.is_none() {
                             break;
                         }
                     }
                     Ok(Token::Comment)
                 } else if let Some('*') = chars.peek() {
+                    // Skip the '*', so that '/*/' does not close the comment
+                    chars.next();
+                    pos += 1;
+
                     loop {
                         let ch = chars.next();
-                        pos += 1;
+                        pos += ch.map_or(0, char::len_utf8);
+                        if ch.is_none() {
+                            break Err(self.error("Unclosed block comment.", start, start + 2)
+                                .with_help("add '*/' to close the comment"));
+                        }
                         if ch == Some('*') {
                             if let Some('/') = chars.peek() {
                                 let _ = chars.next();
-                                break;
+                                pos += 1;
+                                break Ok(Token::Comment);
                             }
                         }
                     }
-                    Ok(Token::Comment)
                 } else {
-                    Ok(Token::Op('/'))
+                    Ok(Token::Op("/".to_owned()))
                 }
 
             },
 
             'a' ..= 'z' | 'A' ..= 'Z' | '_' => {
                 // Parse identifier
-                loop {
-                    let ch = match chars.peek() {
-                        Some(ch) => *ch,
-                        None => return Ok(Token::EOF)
-                    };
-
-                    // A word-like identifier only contains underscores and alphanumeric characters.
-                    if ch != '_' && ch != ',' && !ch.is_alphanumeric() {
+                while let Some(&ch) = chars.peek() {
+                    // A word-like identifier only contains underscores and alphanumeric characters,
+                    // but can end with a comma when it is called, such as 'println,(...)'.
+                    // Within parentheses or brackets, such as 'f(x,(1, 2))', the comma separates elements.
+                    let is_comma_suffix = ch == ',' && src[pos + 1..].starts_with(&['(', '!'][..])
+                        && !src[..start].trim_end().ends_with(&['(', '[', ','][..]);
+
+                    if ch != '_' && !is_comma_suffix && !ch.is_alphanumeric() {
                         break;
                     }
 
                     chars.next();
-                    pos += 1;
+                    pos += ch.len_utf8();
                 }
 
                 match &src[start..pos] {
                     "fn" => Ok(Token::Fn),
+                    "ext" => Ok(Token::Ext),
                     "extern" => Ok(Token::Extern),
                     "if" => Ok(Token::If),
                     "then" => Ok(Token::Then),
@@ -208,6 +361,11 @@ impl<'a> Lexer<'a> {
                     "unary" => Ok(Token::Unary),
                     "binary" => Ok(Token::Binary),
                     "var" => Ok(Token::Var),
+                    "let" => Ok(Token::Let),
+                    "match" => Ok(Token::Match),
+                    "true" => Ok(Token::Bool(true)),
+                    "false" => Ok(Token::Bool(false)),
+                    "struct" => Ok(Token::Struct),
                     "mot" => Ok(Token::Mot),
                     "lru" => Ok(Token::Lru),
                     "mru" => Ok(Token::Mru),
@@ -220,58 +378,335 @@ impl<'a> Lexer<'a> {
 
             op => {
                 // Parse operator
-                Ok(Token::Op(op))
+                Ok(Token::Op(op.to_string()))
             }
         };
 
         // Update stored position, and return
         self.pos = pos;
 
-        result
+        self.last_end = pos;
+
+        let is_field = self.field_dot;
+
+        self.field_dot = self.has_fields && result == Ok(Token::Dot);
+        self.has_fields = match result {
+            Ok(Token::Ident(_) | Token::RParen | Token::RBracket | Token::RBrace) => true,
+            Ok(Token::Int(..)) => is_field,
+            _ => false
+        };
+
+        result.map(|token| Spanned { node: token, span: self.span(start, pos) })
     }
 
-    fn read_escaped_char(&mut self) -> Result<char, LexError> {
-        if let Some(ch) = self.chars.next() {
-            if ch == '\\' {
-                let ch = self.chars.next().ok_or(LexError::new("no input"))?;
-
-                match ch {
-                    '\\' => Ok('\\'),
-                    'n' => Ok('\n'),
-                    't' => Ok('\t'),
-                    'r' => Ok('\r'),
-                    '\"' => Ok('\"'),
-                    '\'' => Ok('\''),
-                    '0' => Ok('\0'),
-
-                    'e' => unimplemented!(),
-                    'v' => unimplemented!(),
-                    'x' => unimplemented!(),
-                    'u' => unimplemented!(),
-
-                    _ => {
-                        Err(LexError::new("unknown escape char"))
-                    }
+    /// Returns the `EOF` token, located right after the last token so that
+    /// errors about a truncated input point at where more input was expected.
+    fn eof(&self) -> Spanned<Token> {
+        Spanned { node: Token::EOF, span: self.span(self.last_end, self.last_end) }
+    }
+
+    /// Lexes the number literal that begins at `start`, returning the position it ends at along with its token.
+    ///
+    /// Integers can be written in hexadecimal (`0xff`), binary (`0b101`) or octal (`0o17`), floats can have
+    /// an exponent (`1.5e-3`), digits can be separated by underscores (`1_000`) and a suffix can give
+    /// the type of the number (`20u8`).
+    fn lex_number(&self, start: usize, is_field: bool) -> (usize, Result<Token, Diagnostic>) {
+        let src = self.input;
+        let bytes = src.as_bytes();
+        let digits_end = |from: usize, radix: u32| {
+            from + src[from..].find(|ch: char| !ch.is_digit(radix) && ch != '_').unwrap_or(src.len() - from)
+        };
+
+        let (radix, name, digits_start) = match src.get(start..start + 2) {
+            Some("0x") => (16, "hexadecimal", start + 2),
+            Some("0b") => (2, "binary", start + 2),
+            Some("0o") => (8, "octal", start + 2),
+            _ => (10, "decimal", start)
+        };
+
+        let mut pos = digits_end(digits_start, radix);
+        let mut is_float = false;
+
+        if radix != 10 {
+            if !src[digits_start..pos].bytes().any(|b| b != b'_') {
+                return (pos, Err(self.error(&format!("Expected {} digits after '{}'.", name, &src[start..digits_start]), start, pos)));
+            }
+
+            // A decimal digit that is not a digit of the radix, such as the '2' of '0b102'
+            if let Some(digit) = src[pos..].chars().next().filter(char::is_ascii_digit) {
+                return (digits_end(pos, 10), Err(self.error(&format!("Invalid digit '{}' in {} literal.", digit, name), pos, pos + 1)));
+            }
+        } else if !is_field {
+            // The fraction starts with a digit, so that '4.twice()' calls a method on '4'
+            if bytes.get(pos) == Some(&b'.') && bytes.get(pos + 1).is_some_and(u8::is_ascii_digit) {
+                pos = digits_end(pos + 1, 10);
+                is_float = true;
+
+                if bytes.get(pos) == Some(&b'.') && bytes.get(pos + 1).is_some_and(u8::is_ascii_digit) {
+                    let end = pos + 1 + src[pos + 1..].find(|ch: char| !ch.is_alphanumeric() && ch != '_' && ch != '.')
+                                                         .unwrap_or(src.len() - pos - 1);
+
+                    return (end, Err(self.error("Number literal has more than one decimal point.", start, end)));
                 }
-            } else {
-                Ok(ch)
             }
-        } else {
-            Err(LexError::new("no input"))
+
+            if let Some(b'e' | b'E') = bytes.get(pos) {
+                let exponent = if let Some(b'+' | b'-') = bytes.get(pos + 1) { pos + 2 } else { pos + 1 };
+                let end = digits_end(exponent, 10);
+
+                if !src[exponent..end].bytes().any(|b| b.is_ascii_digit()) {
+                    return (end, Err(self.error("Expected digits in the exponent of the number literal.", start, end)
+                        .with_help("write the exponent like in '1.5e-3'")));
+                }
+
+                pos = end;
+                is_float = true;
+            }
+        }
+
+        let end = pos + src[pos..].find(|ch: char| !ch.is_alphanumeric() && ch != '_').unwrap_or(src.len() - pos);
+        let suffix = &src[pos..end];
+        let digits = src[digits_start..pos].replace('_', "");
+
+        if !suffix.is_empty() && !NUMERIC_TYPES.contains(&suffix) {
+            return (end, Err(self.error(&format!("Invalid suffix '{}' for a number literal.", suffix), pos, end)
+                .with_help(format!("the suffixes are the numeric types: {}", NUMERIC_TYPES.join(", ")))));
+        }
+
+        if is_float || suffix == "f64" {
+            if radix != 10 {
+                return (end, Err(self.error("Float literals must be written in decimal.", start, end)));
+            }
+
+            if !suffix.is_empty() && suffix != "f64" {
+                return (end, Err(self.error(&format!("Float literal cannot have the suffix '{}'.", suffix), pos, end)
+                    .with_help(format!("convert it explicitly, like in '{}({})'", suffix, &src[start..pos]))));
+            }
+
+            return match digits.parse() {
+                Ok(value) => (end, Ok(Token::Float(value))),
+                Err(_) => (end, Err(self.error("Invalid float literal.", start, end)))
+            };
+        }
+
+        let value = match u64::from_str_radix(&digits, radix) {
+            Ok(value) => value,
+            Err(_) => return (end, Err(self.error("Integer literal is too large.", start, end)
+                .with_help(format!("the largest integer is {}", u64::MAX))))
+        };
+
+        // The parser checks that the value fits in the suffix, once a leading '-' has been applied to it
+        let suffix = if suffix.is_empty() { None } else { Some(suffix.to_owned()) };
+
+        (end, Ok(Token::Int(value, suffix)))
+    }
+
+    /// Reads a character of the string or character literal that begins at `start`, unescaping it if
+    /// needed. Returns `None` when reading the unescaped `quote` that closes the literal.
+    fn read_escaped_char(&mut self, start: usize, quote: char) -> Result<Option<char>, Diagnostic> {
+        let ch = match self.chars.next() {
+            Some(ch) => ch,
+            None => return Err(self.unclosed_literal(start, quote))
+        };
+
+        self.pos += ch.len_utf8();
+
+        if ch == quote {
+            return Ok(None);
+        }
+
+        if ch != '\\' {
+            return Ok(Some(ch));
+        }
+
+        let ch = match self.chars.next() {
+            Some(ch) => ch,
+            None => return Err(self.unclosed_literal(start, quote))
+        };
+
+        self.pos += ch.len_utf8();
+
+        match ch {
+            '\\' => Ok(Some('\\')),
+            'n' => Ok(Some('\n')),
+            't' => Ok(Some('\t')),
+            'r' => Ok(Some('\r')),
+            '\"' => Ok(Some('\"')),
+            '\'' => Ok(Some('\'')),
+            '0' => Ok(Some('\0')),
+            'e' => Ok(Some('\x1b')),
+            'v' => Ok(Some('\x0b')),
+            'x' => self.read_hex_escape().map(Some),
+            'u' => self.read_unicode_escape().map(Some),
+
+            _ => {
+                Err(self.error("Unknown escape character.", self.pos - ch.len_utf8() - 1, self.pos)
+                        .with_help("valid escapes are \\\\, \\n, \\t, \\r, \\e, \\v, \\\", \\', \\0, \\x7f and \\u{1F600}"))
+            }
+        }
+    }
+
+    /// Reads the two hexadecimal digits of a `\x7f` escape, which denotes an ASCII character.
+    fn read_hex_escape(&mut self) -> Result<char, Diagnostic> {
+        let escape_start = self.pos - 2;
+        let mut value = 0;
+
+        for _ in 0..2 {
+            match self.chars.peek().and_then(|ch| ch.to_digit(16)) {
+                Some(digit) => {
+                    self.chars.next();
+                    self.pos += 1;
+
+                    value = value * 16 + digit;
+                },
+                None => return Err(self.error("Expected two hexadecimal digits after '\\x'.", escape_start, self.pos)
+                    .with_help("write the escape like '\\x1b'"))
+            }
+        }
+
+        if value > 0x7f {
+            return Err(self.error("Hexadecimal escape is out of range.", escape_start, self.pos)
+                .with_help("'\\x' escapes are at most '\\x7f'; use '\\u{...}' for other characters"));
         }
+
+        Ok(value as u8 as char)
+    }
+
+    /// Reads the braced hexadecimal digits of a `\u{1F600}` escape, which denotes a Unicode scalar value.
+    fn read_unicode_escape(&mut self) -> Result<char, Diagnostic> {
+        let escape_start = self.pos - 2;
+        let malformed = |lexer: &Lexer| lexer.error("Malformed unicode escape.", escape_start, lexer.pos)
+            .with_help("write the escape with 1 to 6 hexadecimal digits, like '\\u{1F600}'");
+
+        if self.chars.peek() != Some(&'{') {
+            return Err(malformed(self));
+        }
+
+        self.chars.next();
+        self.pos += 1;
+
+        let mut value: u32 = 0;
+        let mut digits = 0;
+
+        loop {
+            match self.chars.peek().copied() {
+                Some('}') if digits > 0 => break,
+                Some(ch) if ch.is_ascii_hexdigit() && digits < 6 => value = value * 16 + ch.to_digit(16).unwrap_or_default(),
+                _ => return Err(malformed(self))
+            }
+
+            self.chars.next();
+            self.pos += 1;
+            digits += 1;
+        }
+
+        self.chars.next();
+        self.pos += 1;
+
+        char::from_u32(value).ok_or_else(|| self.error(&format!("Unicode escape {:X} is not a Unicode scalar value.", value), escape_start, self.pos)
+            .with_help("surrogates, from D800 to DFFF, and values above 10FFFF are not characters"))
+    }
+
+    fn unclosed_literal(&self, start: usize, quote: char) -> Diagnostic {
+        let kind = if quote == '"' { "string" } else { "character" };
+
+        self.error(&format!("Unclosed {} literal.", kind), start, start + 1)
+            .with_help(format!("add a closing quote at the end of the {}", kind))
     }
 }
 
 impl<'a> Iterator for Lexer<'a> {
-    type Item = Token;
+    type Item = Spanned<Token>;
 
     /// Lexes the next `Token` and returns it.
     /// On EOF or failure, `None` will be returned.
     fn next(&mut self) -> Option<Self::Item> {
         match self.lex() {
-            Ok(Token::EOF) | Err(_) => None,
+            Ok(Spanned { node: Token::EOF, .. }) | Err(_) => None,
             Ok(token) => Some(token)
         }
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Token::*;
+
+    /// Lexes a whole source, returning its tokens, or the message of the first error.
+    fn lex(source: &str) -> Result<Vec<Token>, String> {
+        let mut lexer = Lexer::new(source);
+        let mut tokens = vec![];
+
+        loop {
+            match lexer.lex() {
+                Ok(token) if token.node == EOF => return Ok(tokens),
+                Ok(token) => tokens.push(token.node),
+                Err(err) => return Err(err.message)
+            }
+        }
+    }
+
+    fn int(nb: u64, suffix: Option<&str>) -> Token {
+        Int(nb, suffix.map(str::to_owned))
+    }
+
+    #[test]
+    fn integers_have_a_radix_separators_and_a_suffix() {
+        assert_eq!(lex("0xff 0b101 0o17 1_000 1__0"),
+                   Ok(vec![int(255, None), int(5, None), int(15, None), int(1000, None), int(10, None)]));
+        assert_eq!(lex("20u8 0x10i64 7u64"),
+                   Ok(vec![int(20, Some("u8")), int(16, Some("i64")), int(7, Some("u64"))]));
+    }
+
+    #[test]
+    fn floats_have_a_fraction_or_an_exponent() {
+        assert_eq!(lex("1.5 1.5e-3 2e3 1.5e+2_0 3f64"),
+                   Ok(vec![Float(1.5), Float(0.0015), Float(2000.0), Float(1.5e20), Float(3.0)]));
+    }
+
+    #[test]
+    fn positional_fields_are_not_floats() {
+        assert_eq!(lex("x.0.1"), Ok(vec![Ident("x".to_owned()), Dot, int(0, None), Dot, int(1, None)]));
+        assert_eq!(lex("(1.5)"), Ok(vec![LParen, Float(1.5), RParen]));
+    }
+
+    #[test]
+    fn invalid_numbers_are_errors() {
+        assert_eq!(lex("1.2.3"), Err("Number literal has more than one decimal point.".to_owned()));
+        assert_eq!(lex("20u16"), Err("Invalid suffix 'u16' for a number literal.".to_owned()));
+        assert_eq!(lex("1.5u8"), Err("Float literal cannot have the suffix 'u8'.".to_owned()));
+        assert_eq!(lex("0x"), Err("Expected hexadecimal digits after '0x'.".to_owned()));
+        assert_eq!(lex("0b102"), Err("Invalid digit '2' in binary literal.".to_owned()));
+        assert_eq!(lex("1e"), Err("Expected digits in the exponent of the number literal.".to_owned()));
+        assert_eq!(lex("99999999999999999999"), Err("Integer literal is too large.".to_owned()));
+    }
+
+    #[test]
+    fn strings_are_unescaped() {
+        assert_eq!(lex(r#""a\x41\u{1F600}\n\t\\\"""#), Ok(vec![Str("aA\u{1F600}\n\t\\\"".to_owned())]));
+    }
+
+    #[test]
+    fn invalid_escapes_are_errors() {
+        assert_eq!(lex(r#""\q""#), Err("Unknown escape character.".to_owned()));
+        assert_eq!(lex(r#""\x80""#), Err("Hexadecimal escape is out of range.".to_owned()));
+        assert_eq!(lex(r#""\u{D800}""#), Err("Unicode escape D800 is not a Unicode scalar value.".to_owned()));
+        assert_eq!(lex("\"abc"), Err("Unclosed string literal.".to_owned()));
+    }
+
+    #[test]
+    fn raw_strings_keep_backslashes_and_quotes() {
+        assert_eq!(lex(r###"r#"a "quoted" \n"# r"b""###),
+                   Ok(vec![Str(r#"a "quoted" \n"#.to_owned()), Str("b".to_owned())]));
+        assert_eq!(lex(r##"r#"a""##), Err("Unclosed raw string literal.".to_owned()));
+    }
+
+    #[test]
+    fn characters_hold_exactly_one_character() {
+        assert_eq!(lex(r"'a' '\'' '\u{e9}'"), Ok(vec![Char('a'), Char('\''), Char('é')]));
+        assert_eq!(lex("'ab'"), Err("Character literal must contain exactly one character.".to_owned()));
+        assert_eq!(lex("''"), Err("Empty character literal.".to_owned()));
+    }
+}
@@ -3,54 +3,290 @@ extern crate rustyline;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
+mod cache;
+mod diag;
+mod eval;
+mod exhaust;
 mod lex;
+mod num;
 mod parse;
+mod typeck;
 
-use lex::*;
+use diag::{color_enabled, Diagnostic, RUNTIME_ERROR};
+use eval::*;
+use lex::{Lexer, Span, Token};
 use parse::*;
-use std::collections::HashMap;
+use std::process;
+use std::thread;
+
+const USAGE: &str = "\
+Usage: reggae [COMMAND]
+
+Commands:
+    run <FILE>                Runs the 'main' function of a file
+    repl [--history <FILE>]   Starts an interactive session (default)
+    check <FILE>              Parses and analyzes a file without running it
+    lex <FILE>                Prints the tokens of a file
+    parse <FILE>              Prints the syntax tree of a file
+    help                      Prints this message";
+
+/// Exit code of a successful command.
+const EXIT_SUCCESS: i32 = 0;
+/// Exit code of a command that reported an error about the source code, or could not read it.
+const EXIT_FAILURE: i32 = 1;
+/// Exit code of a malformed command line.
+const EXIT_USAGE: i32 = 2;
 
 fn main() {
-    // Build precedence map
-    let mut prec = HashMap::with_capacity(6);
+    // The evaluator recurses on every call, so commands run on a thread with a large enough stack
+    let command = thread::Builder::new().stack_size(STACK_SIZE).spawn(command);
+
+    match command.map(thread::JoinHandle::join) {
+        Ok(Ok(code)) => process::exit(code),
+        _ => process::exit(EXIT_FAILURE)
+    }
+}
+
+/// Runs the command given on the command line, returning its exit code.
+fn command() -> i32 {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    match args.as_slice() {
+        [] | ["repl"] => repl(".reggae.history"),
+        ["repl", "--history", path] => repl(path),
+        ["run", path] => with_source(path, run),
+        ["check", path] => with_source(path, check),
+        ["lex", path] => with_source(path, lex),
+        ["parse", path] => with_source(path, parse),
+        ["help"] | ["--help"] | ["-h"] => {
+            println!("{}", USAGE);
+            EXIT_SUCCESS
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            EXIT_USAGE
+        }
+    }
+}
+
+/// Prints the diagnostics about a source file as they are reported, counting the errors.
+struct Reporter<'a> {
+    source: &'a str,
+    path: &'a str,
+    errors: usize,
+}
+
+impl<'a> Reporter<'a> {
+    fn report(&mut self, diagnostic: Diagnostic) {
+        if diagnostic.is_error() {
+            self.errors += 1;
+        }
+
+        eprint!("{}", diagnostic.render(self.source, self.path, color_enabled()));
+    }
+}
+
+/// Reads the file at `path` and passes its content to `command`, reporting
+/// the diagnostics it emits. Returns the exit code of the command.
+fn with_source(path: &str, command: fn(&str, &mut Reporter)) -> i32 {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("error: cannot read '{}': {}", path, err);
+            return EXIT_FAILURE;
+        }
+    };
+
+    let mut reporter = Reporter { source: &source, path, errors: 0 };
+
+    command(&source, &mut reporter);
+
+    if reporter.errors == 0 { EXIT_SUCCESS } else { EXIT_FAILURE }
+}
+
+/// Parses, analyzes and loads a program without running it, returning
+/// the evaluator it was loaded in if no error was reported.
+fn load(source: &str, reporter: &mut Reporter) -> Option<Evaluator> {
+    let mut evaluator = Evaluator::new();
+
+    let program = match Parser::new(source.to_owned()).parse_program() {
+        Ok(program) => program,
+        Err(err) => {
+            reporter.report(err);
+            return None;
+        }
+    };
+
+    let errors = reporter.errors;
+
+    for diagnostic in evaluator.load(program) {
+        reporter.report(diagnostic);
+    }
+
+    if reporter.errors == errors { Some(evaluator) } else { None }
+}
+
+/// Runs the `main` function of a program.
+fn run(source: &str, reporter: &mut Reporter) {
+    let mut evaluator = match load(source, reporter) {
+        Some(evaluator) => evaluator,
+        None => return
+    };
 
-    prec.insert('=', 2);
-    prec.insert('<', 10);
-    prec.insert('+', 20);
-    prec.insert('-', 20);
-    prec.insert('*', 40);
-    prec.insert('/', 40);
+    match evaluator.run("main") {
+        Some(Ok(_)) => (),
+        Some(Err(err)) => reporter.report(err),
+        None => reporter.report(Diagnostic::error("No 'main' function found.", Span { line: 1, col: 1, ..Span::default() })
+                                    .with_code(RUNTIME_ERROR)
+                                    .with_help("add a function to start from, such as 'fn main() { ... }'"))
+    }
+
+    print_cache_report(&evaluator);
+}
+
+/// Checks that a program is valid, without running it.
+fn check(source: &str, reporter: &mut Reporter) {
+    load(source, reporter);
+}
+
+/// Prints the tokens of a source file, one per line, along with their location.
+fn lex(source: &str, reporter: &mut Reporter) {
+    let mut lexer = Lexer::new(source);
+
+    loop {
+        match lexer.lex() {
+            Ok(token) if token.node == Token::EOF => return,
+            Ok(token) => println!("{}\t{:?}", token.span, token.node),
+            Err(err) => return reporter.report(err)
+        }
+    }
+}
+
+/// Prints the syntax tree of a source file.
+fn parse(source: &str, reporter: &mut Reporter) {
+    match Parser::new(source.to_owned()).parse_program() {
+        Ok(program) => println!("{:#?}", program),
+        Err(err) => reporter.report(err)
+    }
+}
+
+/// Runs the interactive prompt, keeping the history of its lines in the file `history`.
+fn repl(history: &str) -> i32 {
+    // The session is a single module, whose operators persist across lines
+    let mut operators = Operators::new();
 
+    // Definitions persist across lines
+    let mut evaluator = Evaluator::new();
+
+    // Every line is kept, so that diagnostics can show the definitions they refer to
+    let mut session = String::new();
+    let mut lines = 0;
 
     // `()` can be used when no completer is required
     let mut rl = Editor::<()>::new();
-    if rl.load_history(".reggae.history").is_err() {
+    if rl.load_history(history).is_err() {
         println!("No previous history.");
     }
-    loop {
+    let code = loop {
         let readline = rl.readline(">>->");
         match readline {
             Ok(line) => {
                 rl.add_history_entry(&line);
-                let res = Lexer::new(&(line.clone() + "\n")).collect::<Vec<Token>>();
-                println!("-> Attempting to parse lexed input: \n{:?}\n", res);
-                let res = Parser::new(line + "\n", &mut prec).parse();
-                println!("-> Attempting to parse lexed input: \n{:?}\n", res);
-
+                if line.trim_start().starts_with(':') {
+                    run_command(&evaluator, &operators, line.trim());
+                    continue;
+                }
+                let offset = session.len();
+                session += line.trim_end_matches('\n');
+                session += "\n";
+                let mut parser = Parser::with_origin(session[offset..].to_owned(), operators.clone(), offset, lines);
+                let res = parser.parse();
+                if res.is_ok() {
+                    operators = parser.into_operators();
+                }
+                let res = res
+                    .and_then(|item| {
+                        let diagnostics = evaluator.analyze(&item);
+                        let failed = diagnostics.iter().any(Diagnostic::is_error);
+                        for diagnostic in diagnostics {
+                            eprint!("{}", diagnostic.render(&session, "<repl>", color_enabled()));
+                        }
+                        if failed { Ok(None) } else { evaluator.eval(item) }
+                    });
+                lines += 1;
+                match res {
+                    Ok(Some(value)) => println!("=> {}", value),
+                    Ok(None) => (),
+                    Err(err) => eprint!("{}", err.render(&session, "<repl>", color_enabled()))
+                }
             },
             Err(ReadlineError::Interrupted) => {
                 println!("CTRL-C");
-                break
+                break EXIT_SUCCESS
             },
             Err(ReadlineError::Eof) => {
                 println!("CTRL-D");
-                break
+                break EXIT_SUCCESS
             },
             Err(err) => {
-                println!("Error: {:?}", err);
-                break
+                eprintln!("error: {}", err);
+                break EXIT_FAILURE
             }
         }
+    };
+    if let Err(err) = rl.save_history(history) {
+        eprintln!("error: cannot save the history to '{}': {}", history, err);
+    }
+    print_cache_report(&evaluator);
+    code
+}
+
+/// Runs a REPL command, such as `:cache factorial`.
+fn run_command(evaluator: &Evaluator, operators: &Operators, line: &str) {
+    let mut words = line.split_whitespace();
+
+    match (words.next(), words.next()) {
+        (Some(":cache"), None) => print_cache_report(evaluator),
+        (Some(":cache"), Some(name)) => match evaluator.cache_stats(name) {
+            Some(stats) => println!("{}: {}", name, stats),
+            None => println!("Error: '{}' is not a memoized function.", name)
+        },
+        (Some(":type"), Some(_)) => print_type(evaluator, operators, line[":type".len()..].trim()),
+        _ => println!("Error: Unknown command '{}'.", line)
+    }
+}
+
+/// Prints the inferred type of an expression, such as `:type |x| x + 1`, without evaluating it.
+fn print_type(evaluator: &Evaluator, operators: &Operators, text: &str) {
+    let expr = match Parser::with_origin(text.to_owned(), operators.clone(), 0, 0).parse() {
+        Ok(Item::Function(Function { body: Some(expr), is_anon: true, .. })) => expr,
+        Ok(_) => return println!("Error: Expected an expression."),
+        Err(err) => return eprint!("{}", err.render(text, "<repl>", color_enabled()))
+    };
+
+    match evaluator.type_of(&expr) {
+        Ok(ty) => println!("{} : {}", text, ty),
+        Err(diagnostics) => for diagnostic in diagnostics {
+            eprint!("{}", diagnostic.render(text, "<repl>", color_enabled()));
+        }
+    }
+}
+
+/// Prints the cache statistics of every memoized function that was called.
+fn print_cache_report(evaluator: &Evaluator) {
+    let report = evaluator.cache_report()
+                          .into_iter()
+                          .filter(|(_, stats)| stats.hits + stats.misses > 0)
+                          .collect::<Vec<_>>();
+
+    if report.is_empty() {
+        return;
+    }
+
+    println!("Cache statistics:");
+
+    for (name, stats) in report {
+        println!("  {}: {}", name, stats);
     }
-    rl.save_history(".reggae.history").unwrap();
-}
\ No newline at end of file
+}
@@ -0,0 +1,193 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Names of the numeric types.
+pub const NUMERIC_TYPES: [&str; 4] = ["u8", "u64", "i64", "f64"];
+
+/// Defines a number of one of the numeric types.
+#[derive(Debug, Clone, Copy)]
+pub enum Number {
+    U8(u8),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    /// An integer literal, which takes the type of the numbers it is combined with.
+    Int(i128),
+}
+
+/// Applies an arithmetic operator to two integers of the same type, returning `None` on overflow.
+macro_rules! checked {
+    ($op:expr, $a:expr, $b:expr) => {
+        match $op {
+            "+" => $a.checked_add($b),
+            "-" => $a.checked_sub($b),
+            "*" => $a.checked_mul($b),
+            "/" => $a.checked_div($b),
+            "%" => $a.checked_rem($b),
+            _ => None
+        }
+    };
+}
+
+impl Number {
+    /// Returns the name of the type of the number; integer literals are `{integer}`.
+    pub fn type_name(self) -> &'static str {
+        match self {
+            Number::U8(_) => "u8",
+            Number::U64(_) => "u64",
+            Number::I64(_) => "i64",
+            Number::F64(_) => "f64",
+            Number::Int(_) => "{integer}"
+        }
+    }
+
+    /// Returns the value of an integer, or `None` for a float.
+    pub fn integer(self) -> Option<i128> {
+        match self {
+            Number::U8(nb) => Some(nb.into()),
+            Number::U64(nb) => Some(nb.into()),
+            Number::I64(nb) => Some(nb.into()),
+            Number::F64(_) => None,
+            Number::Int(nb) => Some(nb)
+        }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        match self {
+            Number::F64(nb) => nb,
+            nb => nb.integer().unwrap_or_default() as f64
+        }
+    }
+
+    /// Returns whether the number can be combined with `other` without a conversion,
+    /// which is the case if they have the same type or if one of them is an integer literal.
+    pub fn is_compatible(self, other: Number) -> bool {
+        matches!((self, other), (Number::Int(_), _) | (_, Number::Int(_))) || self.type_name() == other.type_name()
+    }
+
+    pub fn is_zero(self) -> bool {
+        match self {
+            Number::F64(nb) => nb == 0.0,
+            nb => nb.integer() == Some(0)
+        }
+    }
+
+    /// Returns the number as an index into a list, if it is a non-negative integer.
+    pub fn as_index(self) -> Option<usize> {
+        self.integer().and_then(|nb| usize::try_from(nb).ok())
+    }
+
+    /// Converts the number to the numeric type `ty`, failing if it does not fit in it.
+    ///
+    /// Floats are truncated toward zero when they are converted to integers.
+    pub fn cast(self, ty: &str) -> Result<Number, String> {
+        let integer = match self {
+            Number::F64(nb) if ty != "f64" => {
+                if !nb.is_finite() {
+                    return Err(format!("Number {} does not fit in '{}'.", nb, ty));
+                }
+
+                // The conversion saturates, and floats beyond the range of 'i128' do not fit in any integer type
+                nb.trunc() as i128
+            },
+            nb => nb.integer().unwrap_or_default()
+        };
+
+        let number = match ty {
+            "f64" => Some(Number::F64(self.to_f64())),
+            "u8" => u8::try_from(integer).ok().map(Number::U8),
+            "u64" => u64::try_from(integer).ok().map(Number::U64),
+            "i64" => i64::try_from(integer).ok().map(Number::I64),
+            _ => return Err(format!("Unknown numeric type '{}'.", ty))
+        };
+
+        number.ok_or_else(|| format!("Number {} does not fit in '{}'.", self, ty))
+    }
+
+    /// Converts two numbers to the same type, which is that of the one that is not an integer literal.
+    fn unify(self, other: Number) -> Result<(Number, Number), String> {
+        match (self, other) {
+            (Number::Int(_), Number::Int(_)) => Ok((self, other)),
+            (Number::Int(_), _) => Ok((self.cast(other.type_name())?, other)),
+            (_, Number::Int(_)) => Ok((self, other.cast(self.type_name())?)),
+            _ if self.type_name() == other.type_name() => Ok((self, other)),
+            _ => Err(format!("Mismatched numbers: '{}' and '{}' have different types.", self.type_name(), other.type_name()))
+        }
+    }
+
+    /// Applies the arithmetic operator `op`, checking that the result of integers does not overflow.
+    pub fn binary(self, op: &str, other: Number) -> Result<Number, String> {
+        let (a, b) = self.unify(other)?;
+
+        if (op == "/" || op == "%") && b.is_zero() && !matches!(b, Number::F64(_)) {
+            return Err(format!("Cannot compute {} {} {}: division by zero.", self, op, other));
+        }
+
+        let result = match (a, b) {
+            (Number::F64(a), Number::F64(b)) => Some(Number::F64(match op {
+                "+" => a + b,
+                "-" => a - b,
+                "*" => a * b,
+                "/" => a / b,
+                "%" => a % b,
+                _ => return Err(format!("Unknown numeric operator '{}'.", op))
+            })),
+            (Number::U8(a), Number::U8(b)) => checked!(op, a, b).map(Number::U8),
+            (Number::U64(a), Number::U64(b)) => checked!(op, a, b).map(Number::U64),
+            (Number::I64(a), Number::I64(b)) => checked!(op, a, b).map(Number::I64),
+            // Integer literals fit in 'i64' or in 'u64'
+            (Number::Int(a), Number::Int(b)) => {
+                checked!(op, a, b).filter(|nb| (i128::from(i64::MIN) ..= i128::from(u64::MAX)).contains(nb)).map(Number::Int)
+            },
+            _ => None
+        };
+
+        result.ok_or_else(|| {
+            let ty = match a {
+                Number::Int(_) => "'i64' or 'u64'".to_owned(),
+                a => format!("'{}'", a.type_name())
+            };
+
+            format!("Integer overflow: {} {} {} does not fit in {}.", self, op, other, ty)
+        })
+    }
+
+    /// Returns the opposite of the number, checking that it does not overflow.
+    pub fn neg(self) -> Result<Number, String> {
+        let result = match self {
+            Number::U8(nb) => 0u8.checked_sub(nb).map(Number::U8),
+            Number::U64(nb) => 0u64.checked_sub(nb).map(Number::U64),
+            Number::I64(nb) => nb.checked_neg().map(Number::I64),
+            Number::F64(nb) => Some(Number::F64(-nb)),
+            Number::Int(nb) => Some(Number::Int(-nb))
+        };
+
+        result.ok_or_else(|| format!("Integer overflow: -{} does not fit in '{}'.", self, self.type_name()))
+    }
+
+    /// Compares two numbers of the same type; floats may be unordered.
+    pub fn compare(self, other: Number) -> Result<Option<Ordering>, String> {
+        let ordering = match self.unify(other)? {
+            (Number::F64(a), Number::F64(b)) => a.partial_cmp(&b),
+            (a, b) => a.integer().partial_cmp(&b.integer())
+        };
+
+        Ok(ordering)
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Number) -> bool {
+        self.compare(*other) == Ok(Some(Ordering::Equal))
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::F64(nb) => write!(f, "{}", nb),
+            nb => write!(f, "{}", nb.integer().unwrap_or_default())
+        }
+    }
+}
@@ -1,9 +1,17 @@
-use crate::lex::{Token, Lexer};
+use crate::diag::{Diagnostic, SYNTAX_ERROR};
+use crate::lex::{Lexer, Span, Spanned, Token};
+use crate::num::Number;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
 use Token::*;
 
 const ANONYMOUS_FUNCTION_NAME: &str = "anonymous";
 
+/// Defines the memoization cache declared by a function, such as `lru!20 fn`.
+///
+/// The size is `-1` when it is omitted, which makes the cache unbounded.
 #[derive(Debug)]
 pub enum Cache {
     Lru(isize),
@@ -13,21 +21,294 @@ pub enum Cache {
     None,
 }
 
-/// Defines a primitive expression.
+/// Defines how a chain of binary operators of the same precedence is grouped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Assoc {
+    /// `a - b - c` is `(a - b) - c`.
+    Left,
+    /// `a = b = c` is `a = (b = c)`.
+    Right,
+    /// `a < b < c` is an error.
+    None,
+}
+
+impl fmt::Display for Assoc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Assoc::Left => write!(f, "left"),
+            Assoc::Right => write!(f, "right"),
+            Assoc::None => write!(f, "none"),
+        }
+    }
+}
+
+/// Defines the precedence and associativity of a binary operator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fixity {
+    pub prec: i32,
+    pub assoc: Assoc,
+}
+
+impl fmt::Display for Fixity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "precedence {} and {} associativity", self.prec, self.assoc)
+    }
+}
+
+/// Defines the binary operators of a module (a source file or a REPL session), along with their fixity.
+///
+/// Every module starts with the builtin operators, and its `binary` declarations do not affect other modules.
+#[derive(Debug, Clone)]
+pub struct Operators {
+    /// The fixity of every operator, along with where it was declared (unless it is builtin).
+    fixities: HashMap<String, (Fixity, Option<Span>)>,
+}
+
+impl Default for Operators {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Operators {
+    /// Creates the operators of a new module, which are the builtin ones.
+    pub fn new() -> Self {
+        Operators { fixities: builtin_fixities().into_iter().map(|(op, fixity)| (op, (fixity, None))).collect() }
+    }
+
+    /// Returns the fixity of an operator, if it has been declared.
+    pub fn get(&self, op: &str) -> Option<Fixity> {
+        self.fixities.get(op).map(|(fixity, _)| *fixity)
+    }
+
+    /// Declares the fixity of an operator at `span`, unless it conflicts with a previous declaration.
+    pub fn declare(&mut self, op: &str, fixity: Fixity, span: Span) -> Result<(), Diagnostic> {
+        match self.fixities.get(op) {
+            Some(&(previous, _)) if previous == fixity => Ok(()),
+
+            Some(&(previous, declared)) => {
+                let err = Diagnostic::error(format!("Operator '{}' is already declared with {}.", op, previous), span)
+                    .with_code(SYNTAX_ERROR);
+
+                Err(match declared {
+                    Some(declared) => err.with_label(declared, "previously declared here"),
+                    None => err.with_note(format!("'{}' is a builtin operator", op))
+                })
+            },
+
+            None => {
+                self.fixities.insert(op.to_owned(), (fixity, Some(span)));
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Returns the fixities of the builtin binary operators, from the loosest to the tightest.
+fn builtin_fixities() -> HashMap<String, Fixity> {
+    let ladder: &[(&[&str], i32, Assoc)] = &[
+        (&["="], 2, Assoc::Right),
+        (&["||"], 4, Assoc::Left),
+        (&["&&"], 6, Assoc::Left),
+        (&["==", "!=", "<", ">", "<=", ">="], 10, Assoc::None),
+        (&["|"], 12, Assoc::Left),
+        (&["^"], 14, Assoc::Left),
+        (&["&"], 16, Assoc::Left),
+        (&["+", "-"], 20, Assoc::Left),
+        (&["*", "/", "%"], 40, Assoc::Left),
+    ];
+
+    ladder.iter()
+          .flat_map(|(ops, prec, assoc)| ops.iter().map(move |op| (op.to_string(), Fixity { prec: *prec, assoc: *assoc })))
+          .collect()
+}
+
+/// Defines the member accessed by a `expr.name` or `expr.0` expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Member {
+    Named(String),
+    Index(usize),
+}
+
+impl fmt::Display for Member {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Member::Named(name) => write!(f, "{}", name),
+            Member::Index(index) => write!(f, "{}", index),
+        }
+    }
+}
+
+/// Defines a primitive expression, along with its location in the source code.
+#[derive(Debug)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span
+}
+
+/// Defines a pattern matched against a value in a `match` arm, along with its location.
+#[derive(Debug)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub span: Span
+}
+
+/// Defines the different kinds of `Pattern`.
+#[derive(Debug)]
+pub enum PatternKind {
+    /// The `_` pattern, which matches anything.
+    Wildcard,
+
+    /// An identifier, which matches anything and binds it.
+    Binding(String),
+
+    Bool(bool),
+    /// An integer, which is negative when it follows a `-`, along with its type suffix if any.
+    Int(i128, Option<String>),
+    Float(f64),
+    Char(char),
+    Str(String),
+
+    /// A `(a, b, ...)` pattern, which matches a tuple of the same length.
+    Tuple(Vec<Pattern>),
+
+    /// A `Name(a, b, ...)` pattern, which matches an instance of a tuple struct.
+    TupleStruct {
+        name: String,
+        fields: Vec<Pattern>
+    },
+
+    /// A `Name { field: pattern, field, .. }` pattern, which matches an instance of a struct.
+    ///
+    /// Every field must be listed, unless the pattern ends with `..`.
+    Struct {
+        name: String,
+        fields: Vec<(String, Pattern)>,
+        rest: bool
+    },
+}
+
+/// Defines a type annotation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    /// A named type, which may be qualified by a path such as `std::stdio::File`.
+    Named(String),
+
+    /// A `&T` thunk, which computes a `T` when it is forced.
+    Ref(Box<Type>),
+
+    /// A `[T]` list.
+    Slice(Box<Type>),
+
+    /// A `{K => V}` map.
+    Map(Box<Type>, Box<Type>),
+
+    /// A `(A, B, ...)` tuple; the empty tuple `()` is the unit type.
+    Tuple(Vec<Type>),
+
+    /// A `fn(A, B) -> R` function or closure; an omitted return type is `()`.
+    Fn {
+        params: Vec<Type>,
+        ret: Box<Type>
+    },
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Named(name) => write!(f, "{}", name),
+            Type::Ref(ty) => write!(f, "&{}", ty),
+            Type::Slice(ty) => write!(f, "[{}]", ty),
+            Type::Map(key, value) => write!(f, "{{{} => {}}}", key, value),
+            Type::Tuple(types) => {
+                write!(f, "(")?;
+
+                for (i, ty) in types.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { "" } else { ", " }, ty)?;
+                }
+
+                write!(f, "{})", if types.len() == 1 { "," } else { "" })
+            },
+            Type::Fn { params, ret } => {
+                write!(f, "fn(")?;
+
+                for (i, ty) in params.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { "" } else { ", " }, ty)?;
+                }
+
+                write!(f, ") -> {}", ret)
+            }
+        }
+    }
+}
+
+/// Defines an arm of a `match` expression.
+#[derive(Debug)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Expr,
+}
+
+/// Defines a statement of a block.
+#[derive(Debug)]
+pub enum Stmt {
+    /// A `let name: Type = expr;` binding, which shadows any previous binding of `name`.
+    Let {
+        name: String,
+        ty: Option<Type>,
+        value: Expr
+    },
+
+    /// An `expr;` statement, whose value is motivated and discarded.
+    Expr(Expr),
+}
+
+/// Defines the different kinds of `Expr`.
 #[derive(Debug)]
-pub enum Expr {
+pub enum ExprKind {
+    /// A call to the closure `callee` evaluates to, such as `make_adder(1)(2)`.
+    Apply {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+        bang: bool,
+    },
+
     Binary {
-        op: char,
+        op: String,
         left: Box<Expr>,
         right: Box<Expr>
     },
 
+    /// A `{ stmt; ... tail }` block, whose value is its tail expression (or `()` if it has none).
+    Block {
+        stmts: Vec<Stmt>,
+        tail: Option<Box<Expr>>
+    },
+
+    Bool(bool),
+
     Call {
         fn_name: String,
         args: Vec<Expr>,
         bang: bool,
     },
 
+    /// A `receiver.method(args)` call, which calls `method(receiver, args)`.
+    MethodCall {
+        receiver: Box<Expr>,
+        method: String,
+        args: Vec<Expr>,
+        bang: bool,
+    },
+
+    /// A `|a, b| body` closure, which captures the scope it is created in.
+    Closure {
+        params: Vec<String>,
+        body: Rc<Expr>
+    },
+
     Conditional {
         cond: Box<Expr>,
         consequence: Box<Expr>,
@@ -42,9 +323,51 @@ pub enum Expr {
         body: Box<Expr>
     },
 
-    Number(f64),
+    /// A `expr.name` or `expr.0` field access.
+    Field {
+        expr: Box<Expr>,
+        member: Member
+    },
+
+    /// An `expr[index]` expression, which can also be assigned to.
+    Index {
+        expr: Box<Expr>,
+        index: Box<Expr>
+    },
+
+    /// A `&expr` expression, which delays `expr` into a thunk.
+    Lazy(Rc<Expr>),
+
+    /// A `[a, b, ...]` list.
+    List(Vec<Expr>),
+
+    /// A `{key => value, ...}` map; the empty map is `{=>}`.
+    Map(Vec<(Expr, Expr)>),
+
+    /// A `match expr { pattern if guard => body, ... }` expression, whose arms are tried in order.
+    Match {
+        expr: Box<Expr>,
+        arms: Vec<Arm>
+    },
+
+    /// A `mot expr` or `expr!` expression, which forces the thunk `expr` evaluates to.
+    Mot(Box<Expr>),
+
+    /// An integer literal, which is negative when it follows a `-`, along with its type suffix if any, such as `20u8`.
+    Int(i128, Option<String>),
+    Float(f64),
+    Char(char),
     Str(String),
 
+    /// A `Name { field: expr, ... }` struct literal.
+    StructLit {
+        name: String,
+        fields: Vec<(String, Expr)>
+    },
+
+    /// A `(a, b, ...)` tuple; the empty tuple `()` is the unit value.
+    Tuple(Vec<Expr>),
+
     Variable(String),
 
     VarIn {
@@ -57,9 +380,10 @@ pub enum Expr {
 #[derive(Debug)]
 pub struct Prototype {
     pub name: String,
-    pub args: Vec<(String, String)>,
-    pub is_op: bool,
-    pub prec: usize,
+    pub args: Vec<(String, Type)>,
+    /// The `-> Type` the function returns, if annotated.
+    pub ret: Option<Type>,
+    pub span: Span,
 }
 
 /// Defines a user-defined or external function.
@@ -71,42 +395,130 @@ pub struct Function {
     pub cache: Cache,
 }
 
+/// Defines the fields of a `struct`, along with their types.
+#[derive(Debug)]
+pub enum Fields {
+    /// The fields of a `struct Name { field: Type, ... }`.
+    Named(Vec<(String, Type)>),
+
+    /// The fields of a `struct Name(Type, ...);`.
+    Tuple(Vec<Type>),
+}
+
+/// Defines a `struct` declaration.
+#[derive(Debug)]
+pub struct Struct {
+    pub name: String,
+    pub fields: Fields,
+    pub span: Span,
+}
+
+impl Struct {
+    /// Returns the position of a member among the fields of the struct, if it has it.
+    pub fn position(&self, member: &Member) -> Option<usize> {
+        match (&self.fields, member) {
+            (Fields::Named(fields), Member::Named(name)) => fields.iter().position(|(field, _)| field == name),
+            (Fields::Tuple(fields), Member::Index(index)) if *index < fields.len() => Some(*index),
+            _ => None
+        }
+    }
+}
+
+/// Defines a top-level item.
+#[derive(Debug)]
+pub enum Item {
+    /// A function definition, an `extern` declaration, or an anonymous top-level expression.
+    Function(Function),
+
+    Struct(Struct),
+
+    /// An `ext path::to::function;` import of a function provided by the runtime.
+    Ext {
+        path: Vec<String>,
+        span: Span
+    }
+}
+
+/// Defines a whole source file, as the list of its items in order.
+#[derive(Debug)]
+pub struct Program {
+    pub items: Vec<Item>
+}
+
 /// Represents the `Expr` parser.
-pub struct Parser<'a> {
-    tokens: Vec<Token>,
+pub struct Parser {
+    tokens: Vec<Spanned<Token>>,
     pos: usize,
-    prec: &'a mut HashMap<char, i32>
+    operators: Operators,
+    lex_error: Option<Diagnostic>,
+    eof: Span,
+    /// The position of the scrutinee of the `match` being parsed, if any.
+    scrutinee: Option<usize>,
 }
 
 // I'm ignoring the 'must_use' lint in order to call 'self.advance' without checking
 // the result when an EOF is acceptable.
 #[allow(unused_must_use)]
-impl<'a> Parser<'a> {
-    /// Creates a new parser, given an input `str` and a `HashMap` binding
-    /// an operator and its precedence in binary expressions.
-    pub fn new(input: String, op_precedence: &'a mut HashMap<char, i32>) -> Self {
-        let mut lexer = Lexer::new(input.as_str());
-        let tokens = lexer.by_ref().collect();
+impl Parser {
+    /// Creates a new parser for the module whose source code is `input`.
+    pub fn new(input: String) -> Self {
+        Parser::with_origin(input, Operators::new(), 0, 0)
+    }
+
+    /// Creates a new parser for an input that begins at the byte `offset` of
+    /// the (0-based) line `line` of a larger source, such as a REPL session,
+    /// given the `operators` declared so far in that source.
+    pub fn with_origin(input: String, operators: Operators, offset: usize, line: usize) -> Self {
+        let mut lexer = Lexer::with_origin(input.as_str(), offset, line);
+        let mut tokens = vec![];
+
+        // Lex until the end of the input, keeping the error (if any) to report it when parsing
+        let (lex_error, eof) = loop {
+            match lexer.lex() {
+                Ok(Spanned { node: EOF, span }) => break (None, span),
+                Ok(Spanned { node: Comment, .. }) => (),
+                Ok(token) => tokens.push(token),
+                Err(err) => {
+                    let span = err.span;
+
+                    break (Some(err), span);
+                }
+            }
+        };
 
         Parser {
-            tokens: tokens,
-            prec: op_precedence,
-            pos: 0
+            tokens,
+            operators,
+            pos: 0,
+            lex_error,
+            eof,
+            scrutinee: None
         }
     }
 
-    /// Parses the content of the parser.
-    pub fn parse(&mut self) -> Result<Function, String> {
+    /// Returns the operators of the module, including the ones declared by the parsed items.
+    pub fn into_operators(self) -> Operators {
+        self.operators
+    }
+
+    /// Parses the content of the parser as a single item, or as a top-level expression.
+    pub fn parse(&mut self) -> Result<Item, Diagnostic> {
+        if let Some(ref err) = self.lex_error {
+            return Err(err.clone());
+        }
+
+        self.declare_operators()?;
+
         let result = match self.current()? {
-            Fn | Lru | Mru | Lfu | Mfu => self.parse_def(),
-            Extern => self.parse_extern(),
-            _ => self.parse_toplevel_expr()
+            Fn | Lru | Mru | Lfu | Mfu | Extern | Ext | Struct => self.parse_item(),
+            _ => self.parse_toplevel_expr().map(Item::Function)
         };
 
         match result {
             Ok(result) => {
                 if !self.at_end() {
-                    Err("Unexpected token after parsed expression.".to_owned())
+                    self.error("Unexpected token after parsed expression.")
+                        .map_err(|err| err.with_help("only one item can be parsed at a time"))
                 } else {
                     Ok(result)
                 }
@@ -116,33 +528,95 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn eat(&mut self, token: Token) -> Result<(), String> {
+    /// Parses the content of the parser as a whole source file.
+    pub fn parse_program(&mut self) -> Result<Program, Diagnostic> {
+        if let Some(ref err) = self.lex_error {
+            return Err(err.clone());
+        }
+
+        self.declare_operators()?;
+
+        let mut items = vec![];
+
+        while !self.at_end() {
+            items.push(self.parse_item()?);
+        }
+
+        Ok(Program { items })
+    }
+
+    /// Parses a top-level item.
+    fn parse_item(&mut self) -> Result<Item, Diagnostic> {
+        let item = match self.curr() {
+            Fn | Lru | Mru | Lfu | Mfu => Item::Function(self.parse_def()?),
+            Extern => Item::Function(self.parse_extern()?),
+            Ext => self.parse_ext()?,
+            Struct => Item::Struct(self.parse_struct()?),
+            _ => return self.error("Expected item ('fn', 'extern', 'ext' or 'struct').")
+                            .map_err(|err| err.with_help("top-level code must be placed in a function, such as 'main'"))
+        };
+
+        // Declarations can optionally be terminated by a semicolon
+        if self.curr() == SemiColon {
+            self.advance();
+        }
+
+        Ok(item)
+    }
+
+    /// Consumes the current `Token`, which must be `token`.
+    fn eat(&mut self, token: Token) -> Result<(), Diagnostic> {
         if self.curr() == token {
-            self.advance()
+            self.pos += 1;
+
+            Ok(())
         } else {
-            Err(format!("Expecting {:?} but found {:?}.", token, self.curr()))
+            self.error(&format!("Expecting {:?} but found {:?}.", token, self.curr()))
         }
     }
 
-    /// Returns the current `Token`, without performing safety checks beforehand.
+    /// Returns the current `Token`, or `EOF` if the end of the input has been reached.
     fn curr(&self) -> Token {
-        self.tokens[self.pos].clone()
+        self.tokens.get(self.pos).map_or(EOF, |token| token.node.clone())
     }
 
     /// Returns the current `Token`, or an error that
     /// indicates that the end of the file has been unexpectedly reached if it is the case.
-    fn current(&self) -> Result<Token, String> {
+    fn current(&self) -> Result<Token, Diagnostic> {
         if self.pos >= self.tokens.len() {
-            Err("Unexpected end of file.".to_owned())
+            self.error("Unexpected end of file.")
         } else {
-            Ok(self.tokens[self.pos].clone())
+            Ok(self.tokens[self.pos].node.clone())
+        }
+    }
+
+    /// Returns the span of the current `Token`, or of the end of the input.
+    fn span(&self) -> Span {
+        self.tokens.get(self.pos).map_or(self.eof, |token| token.span)
+    }
+
+    /// Returns the span going from `start` to the end of the last consumed `Token`.
+    fn span_from(&self, start: Span) -> Span {
+        match self.pos.checked_sub(1).and_then(|prev| self.tokens.get(prev)) {
+            Some(prev) => start.to(prev.span),
+            None => start
         }
     }
 
+    /// Creates an `Expr` spanning from `start` to the last consumed `Token`.
+    fn expr(&self, kind: ExprKind, start: Span) -> Expr {
+        Expr { kind, span: self.span_from(start) }
+    }
+
+    /// Returns an error located at the current `Token`.
+    fn error<T>(&self, msg: &str) -> Result<T, Diagnostic> {
+        Err(Diagnostic::error(msg, self.span()).with_code(SYNTAX_ERROR))
+    }
+
     /// Advances the position, and returns an empty `Result` whose error
     /// indicates that the end of the file has been unexpectedly reached.
     /// This allows to use the `self.advance()?;` syntax.
-    fn advance(&mut self) -> Result<(), String> {
+    fn advance(&mut self) -> Result<(), Diagnostic> {
         let npos = self.pos + 1;
 
         self.pos = npos;
@@ -150,7 +624,7 @@ impl<'a> Parser<'a> {
         if npos < self.tokens.len() {
             Ok(())
         } else {
-            Err("Unexpected end of file.".to_owned())
+            self.error("Unexpected end of file.")
         }
     }
 
@@ -160,152 +634,333 @@ impl<'a> Parser<'a> {
         self.pos >= self.tokens.len()
     }
 
-    /// Returns the precedence of the current `Token`, or 0 if it is not recognized as a binary operator.
-    fn get_tok_precedence(&self) -> i32 {
-        if let Ok(Token::Op(op)) = self.current() {
-            *self.prec.get(&op).unwrap_or(&100)
-        } else {
-            -1
+    /// Returns the current `Token` and its fixity if it is a binary operator,
+    /// or an error if it is an operator that has not been declared.
+    fn binary_op(&self) -> Result<Option<(String, Fixity)>, Diagnostic> {
+        match self.curr() {
+            Op(op) => match self.operators.get(&op) {
+                Some(fixity) => Ok(Some((op, fixity))),
+                None => self.error(&format!("Operator '{}' is not declared.", op))
+                            .map_err(|err| err.with_help(format!("declare it like 'fn binary {} 50 (a: T, b: T) -> T {{ ... }}'", op)))
+            },
+
+            _ => Ok(None)
         }
     }
 
-    /// Parses the prototype of a function, whether external or user-defined.
-    fn parse_prototype(&mut self) -> Result<Prototype, String> {
-        let (id, is_operator, precedence) = match self.curr() {
-            Ident(id) => {
-                self.advance()?;
+    /// Parses the operator and fixity of a `binary` declaration, such as `binary @ 50 left`, and declares them.
+    fn parse_fixity(&mut self) -> Result<(String, Fixity), Diagnostic> {
+        // Eat 'binary' token
+        self.advance()?;
+
+        let op_span = self.span();
+
+        let op = match self.curr() {
+            Op(op) => op,
+            _ => return self.error("Expected operator in custom operator declaration.")
+        };
+
+        self.advance()?;
+
+        let prec = match self.curr() {
+            Int(prec, None) => match i32::try_from(prec) {
+                Ok(prec) => {
+                    self.advance()?;
 
-                (id, false, 0)
+                    Some(prec)
+                },
+                Err(_) => return self.error(&format!("Precedence {} is too large.", prec))
+                                     .map_err(|err| err.with_help(format!("precedences are at most {}", i32::MAX)))
             },
 
-            Binary => {
-                self.advance()?;
+            _ => None
+        };
 
-                let op = match self.curr() {
-                    Op(ch) => ch,
-                    _ => return Err("Expected operator in custom operator declaration.".to_owned())
-                };
+        let assoc = match self.curr() {
+            Ident(ref assoc) if assoc == "left" => Some(Assoc::Left),
+            Ident(ref assoc) if assoc == "right" => Some(Assoc::Right),
+            Ident(ref assoc) if assoc == "none" => Some(Assoc::None),
+            Ident(_) => return self.error("Expected 'left', 'right' or 'none' associativity.")
+                                   .map_err(|err| err.with_help(format!("declare the operator like 'binary {} 50 left (...)'", op))),
+            _ => None
+        };
 
-                self.advance()?;
+        if assoc.is_some() {
+            self.advance()?;
+        }
 
-                let mut name = String::from("binary");
+        // Omitted parts are inherited from a previous declaration of the operator
+        let fixity = match (self.operators.get(&op), prec) {
+            (Some(previous), None) => Fixity { prec: previous.prec, assoc: assoc.unwrap_or(previous.assoc) },
+            (Some(previous), Some(prec)) if previous.prec == prec => Fixity { prec, assoc: assoc.unwrap_or(previous.assoc) },
+            (_, Some(prec)) => Fixity { prec, assoc: assoc.unwrap_or(Assoc::Left) },
+            (None, None) => return Err(Diagnostic::error(format!("Operator '{}' must be declared with a precedence.", op), op_span)
+                                           .with_code(SYNTAX_ERROR)
+                                           .with_help(format!("declare the operator like 'binary {} 50 (...)'", op)))
+        };
 
-                name.push(op);
+        self.operators.declare(&op, fixity, op_span)?;
 
-                let prec = if let Number(prec) = self.curr() {
-                    self.advance()?;
+        Ok((op, fixity))
+    }
 
-                    prec as usize
-                } else {
-                    0
-                };
+    /// Declares the fixity of every `binary` operator of the input before parsing it,
+    /// so that an operator can be used before the function that declares it.
+    fn declare_operators(&mut self) -> Result<(), Diagnostic> {
+        let declarations = self.tokens.iter()
+                                      .enumerate()
+                                      .filter(|(_, token)| token.node == Binary)
+                                      .map(|(pos, _)| pos)
+                                      .collect::<Vec<_>>();
+
+        for pos in declarations {
+            self.pos = pos;
+            self.parse_fixity()?;
+        }
+
+        self.pos = 0;
+
+        Ok(())
+    }
+
+    /// Parses the prototype of a function, whether external or user-defined.
+    fn parse_prototype(&mut self) -> Result<Prototype, Diagnostic> {
+        let start = self.span();
 
-                self.prec.insert(op, prec as i32);
+        let id = match self.curr() {
+            Ident(id) => {
+                self.advance()?;
 
-                (name, true, prec)
+                id
             },
 
+            Binary => format!("binary{}", self.parse_fixity()?.0),
+
             Unary => {
                 self.advance()?;
 
                 let op = match self.curr() {
-                    Op(ch) => ch,
-                    _ => return Err("Expected operator in custom operator declaration.".to_owned())
+                    Op(op) => op,
+                    Bang => "!".to_owned(),
+                    _ => return self.error("Expected operator in custom operator declaration.")
                 };
 
-                let mut name = String::from("unary");
-
-                name.push(op);
-
                 self.advance()?;
 
-                (name, true, 0)
+                format!("unary{}", op)
             },
 
-            _ => return Err("Expected identifier in prototype declaration.".to_owned())
+            _ => return self.error("Expected identifier in prototype declaration.")
         };
 
-        self.eat(LParen);
-
-        if let RParen = self.curr() {
-            self.advance();
-
-            return Ok(Prototype {
-                name: id,
-                args: vec![],
-                is_op: is_operator,
-                prec: precedence
-            });
-        }
+        self.eat(LParen)?;
 
         let mut args = vec![];
 
-        loop {
+        while self.current()? != RParen {
             match self.curr() {
                 Ident(name) => {
-                    self.advance();
-                    self.eat(Token::Colon);
-                    match self.curr() {
-                        Ident(ty) => {
-                            args.push((name, ty));
-                        }
-                        _ => return Err("Expected type".to_owned())
-                    }
+                    self.advance()?;
+                    self.eat(Token::Colon)?;
+                    args.push((name, self.parse_type()?));
                 }
-                _ => return Err("Expected identifier in parameter declaration.".to_owned())
+                _ => return self.error("Expected identifier in parameter declaration.")
             }
 
-            self.advance()?;
-
-            match self.curr() {
-                RParen => {
-                    self.advance();
-                    break;
-                },
-                Comma => {
-                    self.advance();
-                },
-                _ => return Err("Expected ',' or ')' character in prototype declaration.".to_owned())
+            match self.current()? {
+                RParen => (),
+                Comma => self.advance()?,
+                _ => return self.error("Expected ',' or ')' character in prototype declaration.")
             }
         }
 
+        self.advance()?;
+
+        let ret = match self.curr() {
+            Arrow => {
+                self.advance()?;
+
+                Some(self.parse_type()?)
+            },
+
+            _ => None
+        };
+
         Ok(Prototype {
             name: id,
             args,
-            is_op: is_operator,
-            prec: precedence
+            ret,
+            span: self.span_from(start)
         })
     }
 
-    /// Parses a user-defined function.
-    fn parse_def(&mut self) -> Result<Function, String> {
-        let cache = match self.curr() {
-            Lru => Cache::Lru({
-                self.advance()?; if Token::Bang == self.curr() {self.advance(); self.parse_number()?} else {-1}
-            }),
-            Mru => Cache::Mru({
-                self.advance()?; if Token::Bang == self.curr() {self.advance(); self.parse_number()?} else {-1}
-            }),
-            Lfu => Cache::Lfu({
-                self.advance()?; if Token::Bang == self.curr() {self.advance(); self.parse_number()?} else {-1}
-            }),
-            Mfu => Cache::Mfu({
-                self.advance()?; if Token::Bang == self.curr() {self.advance(); self.parse_number()?} else {-1}
-            }),
-            Fn => { Cache::Lru(-1)}
-            _ => return Err("Wrong function decl keyword".to_owned()),
-        };
+    /// Parses a type, such as `u64`, `&[str]`, `{str => &bool}` or `fn(u64) -> bool`.
+    fn parse_type(&mut self) -> Result<Type, Diagnostic> {
+        let start = self.span();
 
-        self.advance()?;
+        match self.current()? {
+            Ident(name) => {
+                let mut path = name;
 
-        // Parse signature of function
-        let proto = self.parse_prototype()?;
-        self.eat(LBrace);
+                self.advance()?;
 
-        // Parse body of function
-        let body = self.parse_expr()?;
+                while self.curr() == PathSep {
+                    self.advance()?;
+
+                    match self.curr() {
+                        Ident(name) => path = format!("{}::{}", path, name),
+                        _ => return self.error("Expected identifier in type path.")
+                    }
+
+                    self.advance()?;
+                }
+
+                Ok(Type::Named(path))
+            },
+
+            // '&&T' is lexed as a single operator
+            Op(ref op) if op == "&" || op == "&&" => {
+                self.advance()?;
+
+                let mut ty = Type::Ref(Box::new(self.parse_type()?));
+
+                if op == "&&" {
+                    ty = Type::Ref(Box::new(ty));
+                }
+
+                Ok(ty)
+            },
+
+            LBracket => {
+                self.advance()?;
+
+                let element = self.parse_type()?;
+
+                match self.current()? {
+                    RBracket => self.advance()?,
+                    _ => return self.error("Expected ']' character at end of list type.")
+                                    .map_err(|err| err.with_label(start, "unclosed delimiter"))
+                }
+
+                Ok(Type::Slice(Box::new(element)))
+            },
+
+            LBrace => {
+                self.advance()?;
+
+                let key = self.parse_type()?;
+
+                match self.current()? {
+                    FatArrow => self.advance()?,
+                    _ => return self.error("Expected '=>' in map type.")
+                }
+
+                let value = self.parse_type()?;
+
+                match self.current()? {
+                    RBrace => self.advance()?,
+                    _ => return self.error("Expected '}' character at end of map type.")
+                                    .map_err(|err| err.with_label(start, "unclosed delimiter"))
+                }
+
+                Ok(Type::Map(Box::new(key), Box::new(value)))
+            },
+
+            LParen => {
+                let (types, trailing_comma) = self.parse_types()?;
+
+                // '(T)' is parenthesized, while '(T,)' is a 1-tuple
+                match types.len() {
+                    1 if !trailing_comma => Ok(types.into_iter().next().unwrap()),
+                    _ => Ok(Type::Tuple(types))
+                }
+            },
+
+            Fn => {
+                self.advance()?;
+
+                if self.curr() != LParen {
+                    return self.error("Expected '(' character in function type.");
+                }
+
+                let (params, _) = self.parse_types()?;
 
-        self.eat(RBrace);
+                let ret = match self.curr() {
+                    Arrow => {
+                        self.advance()?;
+
+                        self.parse_type()?
+                    },
+
+                    _ => Type::Tuple(vec![])
+                };
+
+                Ok(Type::Fn { params, ret: Box::new(ret) })
+            },
+
+            _ => self.error("Expected type.")
+        }
+    }
+
+    /// Parses a parenthesized list of types, returning whether it ends with a trailing comma.
+    fn parse_types(&mut self) -> Result<(Vec<Type>, bool), Diagnostic> {
+        let start = self.span();
+
+        // Eat '(' token
+        self.advance()?;
+
+        let mut types = vec![];
+        let mut trailing_comma = false;
+
+        while self.current()? != RParen {
+            types.push(self.parse_type()?);
+
+            trailing_comma = false;
+
+            match self.current()? {
+                Comma => {
+                    self.advance()?;
+
+                    trailing_comma = true;
+                },
+                RParen => (),
+                _ => return self.error("Expected ',' or ')' character in types.")
+                                .map_err(|err| err.with_label(start, "unclosed delimiter"))
+            }
+        }
+
+        // Eat ')' token
+        self.advance();
+
+        Ok((types, trailing_comma))
+    }
+
+    /// Parses a user-defined function.
+    fn parse_def(&mut self) -> Result<Function, Diagnostic> {
+        let cache = match self.curr() {
+            Lru => Cache::Lru({
+                self.advance()?; if Token::Bang == self.curr() {self.advance(); self.parse_number()?} else {-1}
+            }),
+            Mru => Cache::Mru({
+                self.advance()?; if Token::Bang == self.curr() {self.advance(); self.parse_number()?} else {-1}
+            }),
+            Lfu => Cache::Lfu({
+                self.advance()?; if Token::Bang == self.curr() {self.advance(); self.parse_number()?} else {-1}
+            }),
+            Mfu => Cache::Mfu({
+                self.advance()?; if Token::Bang == self.curr() {self.advance(); self.parse_number()?} else {-1}
+            }),
+            Fn => Cache::None,
+            _ => return self.error("Wrong function decl keyword"),
+        };
+
+        self.advance()?;
+
+        // Parse signature of function
+        let proto = self.parse_prototype()?;
+
+        // Parse body of function
+        let body = self.parse_block()?;
 
         // Return new function
         Ok(Function {
@@ -317,7 +972,7 @@ impl<'a> Parser<'a> {
     }
 
     /// Parses an external function declaration.
-    fn parse_extern(&mut self) -> Result<Function, String> {
+    fn parse_extern(&mut self) -> Result<Function, Diagnostic> {
         // Eat 'extern' keyword
         self.pos += 1;
 
@@ -332,164 +987,681 @@ impl<'a> Parser<'a> {
         })
     }
 
-    /// Parses any expression.
-    fn parse_expr(&mut self) -> Result<Expr, String> {
-        match self.parse_unary_expr() {
-            Ok(left) => self.parse_binary_expr(0, left),
-            err => err
-        }
-    }
+    /// Parses an `ext path::to::function;` import.
+    fn parse_ext(&mut self) -> Result<Item, Diagnostic> {
+        let start = self.span();
+
+        // Eat 'ext' keyword
+        self.advance()?;
+
+        let mut path = vec![];
+
+        loop {
+            match self.curr() {
+                Ident(name) => path.push(name),
+                _ => return self.error("Expected identifier in 'ext' path.")
+            }
+
+            self.advance();
+
+            if self.curr() != PathSep {
+                break;
+            }
+
+            self.advance()?;
+        }
+
+        let span = self.span_from(start);
+
+        self.eat(SemiColon)?;
+
+        Ok(Item::Ext { path, span })
+    }
+
+    /// Parses a `struct` declaration, with either named or positional fields.
+    fn parse_struct(&mut self) -> Result<Struct, Diagnostic> {
+        let start = self.span();
+
+        // Eat 'struct' keyword
+        self.advance()?;
+
+        let name = match self.curr() {
+            Ident(name) => name,
+            _ => return self.error("Expected identifier in struct declaration.")
+        };
+
+        self.advance()?;
+
+        let fields = match self.curr() {
+            LParen => {
+                self.advance()?;
+
+                let mut types = vec![];
+
+                while self.curr() != RParen {
+                    types.push(self.parse_type()?);
+
+                    match self.curr() {
+                        Comma => self.advance()?,
+                        RParen => (),
+                        _ => return self.error("Expected ',' or ')' character in struct declaration.")
+                    }
+                }
+
+                self.advance();
+
+                Fields::Tuple(types)
+            },
+
+            LBrace => {
+                self.advance()?;
+
+                let mut fields: Vec<(String, Type)> = vec![];
+
+                while self.curr() != RBrace {
+                    let field = match self.curr() {
+                        Ident(field) => field,
+                        _ => return self.error("Expected identifier in field declaration.")
+                    };
+
+                    if fields.iter().any(|(name, _)| *name == field) {
+                        return self.error(&format!("Field '{}' is already declared.", field));
+                    }
+
+                    self.advance()?;
+                    self.eat(Colon)?;
+
+                    fields.push((field, self.parse_type()?));
+
+                    match self.curr() {
+                        Comma => self.advance()?,
+                        RBrace => (),
+                        _ => return self.error("Expected ',' or '}' character in struct declaration.")
+                    }
+                }
+
+                self.advance();
+
+                Fields::Named(fields)
+            },
+
+            _ => return self.error("Expected '(' or '{' character in struct declaration.")
+        };
+
+        Ok(Struct { name, fields, span: self.span_from(start) })
+    }
+
+    /// Parses any expression.
+    fn parse_expr(&mut self) -> Result<Expr, Diagnostic> {
+        match self.parse_unary_expr() {
+            Ok(left) => self.parse_binary_expr(0, left),
+            err => err
+        }
+    }
+
+    /// Parses a literal integer.
+    fn parse_number(&mut self) -> Result<isize, Diagnostic> {
+        match self.curr() {
+            Int(nb, None) => match isize::try_from(nb) {
+                Ok(nb) => {
+                    self.advance();
+                    Ok(nb)
+                },
+                Err(_) => self.error(&format!("Integer literal is too large, the largest is {}.", isize::MAX))
+            },
+            _ => self.error("Expected integer literal.")
+        }
+    }
+
+    /// Returns an error spanning from `start` if the integer literal `nb` does not fit in the type `suffix`.
+    fn check_int(&self, nb: i128, suffix: &Option<String>, start: Span) -> Result<(), Diagnostic> {
+        match suffix.as_ref().map(|ty| Number::Int(nb).cast(ty)) {
+            Some(Err(msg)) => Err(Diagnostic::error(msg, self.span_from(start)).with_code(SYNTAX_ERROR)),
+            _ => Ok(())
+        }
+    }
+
+    /// Parses a literal number.
+    fn parse_nb_expr(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span();
+
+        // Simply convert Token::Int and Token::Float to Expr::Int and Expr::Float
+        match self.curr() {
+            Int(nb, suffix) => {
+                self.advance();
+                self.check_int(nb.into(), &suffix, start)?;
+
+                Ok(self.expr(ExprKind::Int(nb.into(), suffix), start))
+            },
+            Float(nb) => {
+                self.advance();
+                Ok(self.expr(ExprKind::Float(nb), start))
+            },
+            _ => self.error("Expected number literal.")
+        }
+    }
+
+    /// Parses a block of `;`-separated statements, with an optional tail expression.
+    fn parse_block(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span();
+
+        self.eat(LBrace)?;
+
+        let mut stmts = vec![];
+        let mut tail = None;
+
+        loop {
+            let token = self.current().map_err(|err| err.with_label(start, "unclosed block"))?;
+
+            match token {
+                RBrace => break,
+
+                // '{=>}' is the empty map
+                FatArrow if stmts.is_empty() => return self.parse_map_lit(None, start),
+
+                Let => {
+                    self.advance()?;
+
+                    let name = match self.curr() {
+                        Ident(name) => name,
+                        _ => return self.error("Expected identifier in 'let' binding.")
+                    };
+
+                    self.advance()?;
+
+                    let ty = match self.curr() {
+                        Colon => {
+                            self.advance()?;
+
+                            Some(self.parse_type()?)
+                        },
+
+                        _ => None
+                    };
+
+                    match self.curr() {
+                        Op(ref op) if op == "=" => self.advance()?,
+                        _ => return self.error("Expected '=' character in 'let' binding.")
+                    }
+
+                    let value = self.parse_expr()?;
+
+                    self.eat(SemiColon)?;
+
+                    stmts.push(Stmt::Let { name, ty, value });
+                },
+
+                _ => {
+                    let expr = self.parse_expr()?;
+
+                    // Like in Rust, expressions that end with a block can omit their semicolon
+                    let ends_with_block = self.tokens[self.pos - 1].node == RBrace;
+
+                    match self.curr() {
+                        // '{ key => value, ... }' is a map literal rather than a block
+                        FatArrow if stmts.is_empty() => return self.parse_map_lit(Some(expr), start),
+
+                        SemiColon => {
+                            self.advance()?;
+                            stmts.push(Stmt::Expr(expr));
+                        },
+                        RBrace => tail = Some(Box::new(expr)),
+                        _ if ends_with_block => stmts.push(Stmt::Expr(expr)),
+                        _ => return self.error("Expected ';' or '}' character after expression.")
+                                        .map_err(|err| err.with_label(start, "block starts here"))
+                    }
+                }
+            }
+        }
+
+        self.advance();
+
+        Ok(self.expr(ExprKind::Block { stmts, tail }, start))
+    }
+
+    /// Parses the rest of a map literal, given its first key if it is not empty.
+    fn parse_map_lit(&mut self, first: Option<Expr>, start: Span) -> Result<Expr, Diagnostic> {
+        let mut entries = vec![];
+        let mut key = first;
+
+        self.eat(FatArrow)?;
+
+        while let Some(k) = key.take() {
+            let value = self.parse_expr()?;
+
+            entries.push((k, value));
+
+            match self.current()? {
+                Comma => {
+                    self.advance()?;
+
+                    // A trailing comma is allowed
+                    if self.curr() != RBrace {
+                        key = Some(self.parse_expr()?);
+
+                        self.eat(FatArrow)?;
+                    }
+                },
+                RBrace => (),
+                _ => return self.error("Expected ',' or '}' character in map.")
+                                .map_err(|err| err.with_label(start, "unclosed delimiter"))
+            }
+        }
+
+        match self.current()? {
+            RBrace => self.advance(),
+            _ => return self.error("Expected '}' character at end of map.")
+                            .map_err(|err| err.with_label(start, "unclosed delimiter"))
+        };
+
+        Ok(self.expr(ExprKind::Map(entries), start))
+    }
+
+    /// Parses an expression enclosed in parenthesis.
+    fn parse_paren_expr(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span();
+
+        match self.current()? {
+            LParen => (),
+            _ => return self.error("Expected '(' character at start of parenthesized expression.")
+        }
+
+        self.advance()?;
+
+        if self.curr() == RParen {
+            self.advance();
+
+            return Ok(self.expr(ExprKind::Tuple(vec![]), start));
+        }
+
+        let expr = self.parse_expr()?;
+
+        match self.current()? {
+            RParen => (),
+            Comma => return self.parse_tuple_expr(expr, start),
+            _ => return self.error("Expected ')' character at end of parenthesized expression.")
+                            .map_err(|err| err.with_label(start, "unclosed delimiter"))
+        }
+
+        self.advance();
+
+        Ok(expr)
+    }
+
+    /// Parses the rest of a tuple, given its first element.
+    fn parse_tuple_expr(&mut self, first: Expr, start: Span) -> Result<Expr, Diagnostic> {
+        let mut elements = vec![first];
+
+        // A trailing comma is allowed, and required by 1-tuples such as '(a,)'
+        while self.current()? == Comma {
+            self.advance()?;
+
+            if self.curr() == RParen {
+                break;
+            }
+
+            elements.push(self.parse_expr()?);
+        }
+
+        match self.current()? {
+            RParen => self.advance(),
+            _ => return self.error("Expected ',' or ')' character in tuple.")
+                            .map_err(|err| err.with_label(start, "unclosed delimiter"))
+        };
+
+        Ok(self.expr(ExprKind::Tuple(elements), start))
+    }
+
+    /// Parses a list, whose elements may be followed by a trailing comma.
+    fn parse_list_expr(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span();
+
+        // Eat '[' token
+        self.advance()?;
+
+        let mut elements = vec![];
+
+        while self.current()? != RBracket {
+            elements.push(self.parse_expr()?);
+
+            match self.current()? {
+                Comma => self.advance()?,
+                RBracket => (),
+                _ => return self.error("Expected ',' or ']' character in list.")
+                                .map_err(|err| err.with_label(start, "unclosed delimiter"))
+            }
+        }
+
+        self.advance();
+
+        Ok(self.expr(ExprKind::List(elements), start))
+    }
+
+    /// Parses an expression that starts with an identifier (either a variable or a function call).
+    fn parse_id_expr(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span();
+
+        let id = match self.curr() {
+            Ident(id) => id,
+            _ => return self.error("Expected identifier.")
+        };
+
+        if self.advance().is_err() {
+            return Ok(self.expr(ExprKind::Variable(id), start));
+        }
+
+        // 'f!(args)' motivates the call just like 'f(args)!'
+        let bang = self.curr() == Bang
+                && self.tokens.get(self.pos + 1).map(|token| &token.node) == Some(&LParen);
+
+        if bang {
+            self.advance()?;
+        }
+
+        // 'Name { field: ... }' is a struct literal, and 'Name {}' an empty one
+        let is_struct_lit = self.curr() == LBrace && !self.in_scrutinee() && match self.tokens.get(self.pos + 1).map(|token| &token.node) {
+            Some(RBrace) => true,
+            Some(Ident(_)) => self.tokens.get(self.pos + 2).map(|token| &token.node) == Some(&Colon),
+            _ => false
+        };
+
+        if is_struct_lit {
+            return self.parse_struct_lit(id, start);
+        }
+
+        match self.curr() {
+            LParen => {
+                let args = self.parse_args()?;
+
+                Ok(self.expr(ExprKind::Call { fn_name: id, args, bang }, start))
+            },
+
+            _ => Ok(self.expr(ExprKind::Variable(id), start))
+        }
+    }
+
+    /// Parses the parenthesized arguments of a call.
+    fn parse_args(&mut self) -> Result<Vec<Expr>, Diagnostic> {
+        let open = self.span();
+
+        // Eat '(' token
+        self.advance()?;
+
+        let mut args = vec![];
+
+        if let RParen = self.curr() {
+            self.advance();
+            return Ok(args);
+        }
+
+        loop {
+            args.push(self.parse_expr()?);
+
+            match self.current()? {
+                Comma => (),
+                RParen => break,
+                _ => return self.error("Expected ',' character in function call.")
+                                .map_err(|err| err.with_label(open, "arguments start here"))
+            }
+
+            self.advance()?;
+        }
+
+        self.advance();
+
+        Ok(args)
+    }
+
+    /// Parses the fields of a struct literal, whose name has already been parsed.
+    fn parse_struct_lit(&mut self, name: String, start: Span) -> Result<Expr, Diagnostic> {
+        let open = self.span();
+
+        // Eat '{' token
+        self.advance()?;
+
+        let mut fields = vec![];
+
+        while self.current()? != RBrace {
+            let field = match self.curr() {
+                Ident(field) => field,
+                _ => return self.error("Expected field name in struct literal.")
+            };
+
+            self.advance()?;
+            self.eat(Colon)?;
+
+            fields.push((field, self.parse_expr()?));
+
+            match self.current()? {
+                Comma => self.advance()?,
+                RBrace => (),
+                _ => return self.error("Expected ',' or '}' character in struct literal.")
+                                .map_err(|err| err.with_label(open, "fields start here"))
+            }
+        }
+
+        self.advance();
+
+        Ok(self.expr(ExprKind::StructLit { name, fields }, start))
+    }
+
+    /// Parses an unary expression.
+    fn parse_unary_expr(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span();
+
+        let op = match self.current()? {
+            Mot => {
+                self.advance()?;
+
+                let expr = self.parse_unary_expr()?;
+
+                return Ok(motivate(expr, self.span_from(start)));
+            },
+            Op(ref op) if op == "|" || op == "||" => return self.parse_closure_expr(),
+            Op(ref op) if op == "&" || op == "&&" => {
+                self.advance()?;
+
+                let mut expr = self.parse_unary_expr()?;
+
+                // '&&expr' delays 'expr' twice
+                if op == "&&" {
+                    expr = self.expr(ExprKind::Lazy(Rc::new(expr)), start);
+                }
+
+                return Ok(self.expr(ExprKind::Lazy(Rc::new(expr)), start));
+            },
+            Bang => {
+                self.advance()?;
+                "!".to_owned()
+            }
+            Op(op) => {
+                self.advance()?;
+                op
+            },
+            _ => return self.parse_postfix_expr()
+        };
+
+        // '-' is folded into an integer literal that stands alone, so that '-9223372036854775808i64' fits
+        if op == "-" {
+            if let Int(nb, suffix) = self.curr() {
+                let next = self.tokens.get(self.pos + 1).map(|token| &token.node);
+
+                if !matches!(next, Some(Bang) | Some(Dot) | Some(LBracket) | Some(LParen)) {
+                    self.advance();
+                    self.check_int(-i128::from(nb), &suffix, start)?;
 
-    /// Parses a literal number.
-    fn parse_number(&mut self) -> Result<isize, String> {
-        // Simply convert Token::Number to Expr::Number
-        match self.curr() {
-            Number(nb) => {
-                self.advance();
-                Ok(nb as isize)
-            },
-            _ => Err("Expected number literal.".to_owned())
+                    return Ok(self.expr(ExprKind::Int(-i128::from(nb), suffix), start));
+                }
+            }
         }
-    }
 
-    /// Parses a literal number.
-    fn parse_nb_expr(&mut self) -> Result<Expr, String> {
-        // Simply convert Token::Number to Expr::Number
-        match self.curr() {
-            Number(nb) => {
-                self.advance();
-                Ok(Expr::Number(nb))
-            },
-            _ => Err("Expected number literal.".to_owned())
-        }
+        let name = format!("unary{}", op);
+
+        let operand = self.parse_unary_expr()?;
+
+        // Operators are applied eagerly, unlike regular calls.
+        Ok(self.expr(ExprKind::Call {
+            fn_name: name,
+            args: vec![ operand ],
+            bang: true,
+        }, start))
     }
 
-    /// Parses an expression enclosed in parenthesis.
-    fn parse_paren_expr(&mut self) -> Result<Expr, String> {
-        match self.current()? {
-            LParen => (),
-            _ => return Err("Expected '(' character at start of parenthesized expression.".to_owned())
-        }
+    /// Parses a `|a, b| body` closure, or a `|| body` closure without parameters.
+    fn parse_closure_expr(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span();
+        let mut params = vec![];
 
-        self.advance()?;
+        if self.curr() == Op("|".to_owned()) {
+            self.advance()?;
 
-        let expr = self.parse_expr()?;
+            while self.current()? != Op("|".to_owned()) {
+                match self.curr() {
+                    Ident(name) => params.push(name),
+                    _ => return self.error("Expected identifier in closure parameters.")
+                }
 
-        match self.current()? {
-            RParen => (),
-            _ => return Err("Expected ')' character at end of parenthesized expression.".to_owned())
+                self.advance()?;
+
+                match self.current()? {
+                    Comma => self.advance()?,
+                    Op(ref op) if op == "|" => (),
+                    _ => return self.error("Expected ',' or '|' character in closure parameters.")
+                                    .map_err(|err| err.with_label(start, "parameters start here"))
+                }
+            }
         }
 
-        self.advance();
+        // Eat the closing '|', or the empty '||'
+        self.advance()?;
 
-        Ok(expr)
+        let body = self.parse_expr()?;
+
+        Ok(self.expr(ExprKind::Closure { params, body: Rc::new(body) }, start))
     }
 
-    /// Parses an expression that starts with an identifier (either a variable or a function call).
-    fn parse_id_expr(&mut self) -> Result<Expr, String> {
-        let id = match self.curr() {
-            Ident(id) => id,
-            _ => return Err("Expected identifier.".to_owned())
-        };
+    /// Parses a primary expression, followed by any number of '!' suffixes, field accesses, calls and indexes.
+    fn parse_postfix_expr(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span();
+        let mut expr = self.parse_primary()?;
 
-        if self.advance().is_err() {
-            return Ok(Expr::Variable(id));
-        }
+        loop {
+            match self.curr() {
+                Bang => {
+                    self.advance();
 
-        match self.curr() {
-            LParen => {
-                self.advance()?;
+                    expr = motivate(expr, self.span_from(start));
+                },
 
-                if let RParen = self.curr() {
+                Dot => {
                     self.advance()?;
-                    let bang = Token::Bang == self.curr();
-                    return Ok(Expr::Call { fn_name: id, args: vec![], bang });
-                }
 
-                let mut args = vec![];
+                    let member = match self.curr() {
+                        Ident(name) => Member::Named(name),
+                        Int(nb, None) => Member::Index(nb as usize),
+                        _ => return self.error("Expected field name or index after '.'.")
+                    };
+
+                    self.advance();
 
-                loop {
-                    args.push(self.parse_expr()?);
+                    // 'x.method!(args)' motivates the call just like 'x.method(args)!'
+                    let bang = self.curr() == Bang
+                            && self.tokens.get(self.pos + 1).map(|token| &token.node) == Some(&LParen);
 
-                    match self.current()? {
-                        Comma => (),
-                        RParen => break,
-                        _ => return Err("Expected ',' character in function call.".to_owned())
+                    if bang {
+                        self.advance()?;
                     }
 
-                    self.advance()?;
-                }
+                    expr = match member {
+                        Member::Named(method) if self.curr() == LParen => {
+                            let args = self.parse_args()?;
 
-                self.advance();
+                            self.expr(ExprKind::MethodCall { receiver: Box::new(expr), method, args, bang }, start)
+                        },
 
-                let bang = Token::Bang == self.curr();
+                        member => self.expr(ExprKind::Field { expr: Box::new(expr), member }, start)
+                    };
+                },
 
-                Ok(Expr::Call { fn_name: id, args: args, bang })
-            },
+                // Expressions that end with a block are statements, rather than callees
+                LParen if self.tokens[self.pos - 1].node != RBrace => {
+                    let args = self.parse_args()?;
 
-            _ => Ok(Expr::Variable(id))
-        }
-    }
+                    expr = self.expr(ExprKind::Apply { callee: Box::new(expr), args, bang: false }, start);
+                },
 
-    /// Parses an unary expression.
-    fn parse_unary_expr(&mut self) -> Result<Expr, String> {
-        let op = match self.current()? {
-            Bang => {
-                self.advance()?;
-                '!'
-            }
-            Op(ch) => {
-                self.advance()?;
-                ch
-            },
-            _ => return self.parse_primary()
-        };
+                // ... and neither are they indexed
+                LBracket if self.tokens[self.pos - 1].node != RBrace => {
+                    let open = self.span();
 
-        let mut name = String::from("unary");
+                    self.advance()?;
 
-        name.push(op);
+                    let index = self.parse_expr()?;
 
-        Ok(Expr::Call {
-            fn_name: name,
-            args: vec![ self.parse_unary_expr()? ],
-            bang: false,
-        })
-    }
+                    match self.current()? {
+                        RBracket => self.advance(),
+                        _ => return self.error("Expected ']' character at end of index.")
+                                        .map_err(|err| err.with_label(open, "unclosed delimiter"))
+                    };
 
-    /// Parses a binary expression, given its left-hand expression.
-    fn parse_binary_expr(&mut self, prec: i32, mut left: Expr) -> Result<Expr, String> {
-        loop {
-            let curr_prec = self.get_tok_precedence();
+                    expr = self.expr(ExprKind::Index { expr: Box::new(expr), index: Box::new(index) }, start);
+                },
 
-            if curr_prec < prec || self.at_end() {
-                return Ok(left);
+                _ => return Ok(expr)
             }
+        }
+    }
 
-            let op = match self.curr() {
-                Op(op) => op,
-                _ => return Err("Invalid operator.".to_owned())
+    /// Parses a binary expression whose operators bind at least as tight as `prec`,
+    /// given its left-hand expression.
+    fn parse_binary_expr(&mut self, prec: i32, mut left: Expr) -> Result<Expr, Diagnostic> {
+        loop {
+            let (op, fixity) = match self.binary_op()? {
+                Some((op, fixity)) if fixity.prec >= prec => (op, fixity),
+                _ => return Ok(left)
             };
 
+            let op_span = self.span();
+
             self.advance()?;
 
             let mut right = self.parse_unary_expr()?;
 
-            let next_prec = self.get_tok_precedence();
+            // Let the operators that bind tighter (or as tight, but to the right) take the right-hand side
+            while let Some((_, next)) = self.binary_op()? {
+                if next.prec > fixity.prec {
+                    right = self.parse_binary_expr(fixity.prec + 1, right)?;
+                } else if next.prec == fixity.prec && fixity.assoc == Assoc::Right {
+                    right = self.parse_binary_expr(fixity.prec, right)?;
+                } else {
+                    break;
+                }
+            }
 
-            if curr_prec < next_prec {
-                right = self.parse_binary_expr(curr_prec + 1, right)?;
+            if fixity.assoc == Assoc::None {
+                if let Some((next_op, next)) = self.binary_op()? {
+                    if next.prec == fixity.prec {
+                        return self.error(&format!("Operators '{}' and '{}' cannot be chained.", op, next_op))
+                                   .map_err(|err| err.with_label(op_span, "first operator")
+                                                     .with_help("use parentheses to group them"));
+                    }
+                }
             }
 
-            left = Expr::Binary {
-                op: op,
+            let start = left.span;
+
+            left = self.expr(ExprKind::Binary {
+                op,
                 left: Box::new(left),
                 right: Box::new(right)
-            };
+            }, start);
         }
     }
 
     /// Parses a conditional if..then..else expression.
-    fn parse_conditional_expr(&mut self) -> Result<Expr, String> {
+    fn parse_conditional_expr(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span();
+
         // eat 'if' token
         self.advance()?;
 
@@ -498,7 +1670,7 @@ impl<'a> Parser<'a> {
         // eat 'then' token
         match self.current() {
             Ok(Then) => self.advance()?,
-            _ => return Err("Expected 'then' keyword.".to_owned())
+            _ => return self.error("Expected 'then' keyword.")
         }
 
         let then = self.parse_expr()?;
@@ -506,26 +1678,28 @@ impl<'a> Parser<'a> {
         // eat 'else' token
         match self.current() {
             Ok(Else) => self.advance()?,
-            _ => return Err("Expected 'else' keyword.".to_owned())
+            _ => return self.error("Expected 'else' keyword.")
         }
 
         let otherwise = self.parse_expr()?;
 
-        Ok(Expr::Conditional {
+        Ok(self.expr(ExprKind::Conditional {
             cond: Box::new(cond),
             consequence: Box::new(then),
             alternative: Box::new(otherwise)
-        })
+        }, start))
     }
 
     /// Parses a loop for..in.. expression.
-    fn parse_for_expr(&mut self) -> Result<Expr, String> {
+    fn parse_for_expr(&mut self) -> Result<Expr, Diagnostic> {
+        let span = self.span();
+
         // eat 'for' token
         self.advance()?;
 
         let name = match self.curr() {
             Ident(n) => n,
-            _ => return Err("Expected identifier in for loop.".to_owned())
+            _ => return self.error("Expected identifier in for loop.")
         };
 
         // eat identifier
@@ -533,8 +1707,8 @@ impl<'a> Parser<'a> {
 
         // eat '=' token
         match self.curr() {
-            Op('=') => self.advance()?,
-            _ => return Err("Expected '=' character in for loop.".to_owned())
+            Op(ref op) if op == "=" => self.advance()?,
+            _ => return self.error("Expected '=' character in for loop.")
         }
 
         let start = self.parse_expr()?;
@@ -542,7 +1716,7 @@ impl<'a> Parser<'a> {
         // eat ',' token
         match self.current()? {
             Comma => self.advance()?,
-            _ => return Err("Expected ',' character in for loop.".to_owned())
+            _ => return self.error("Expected ',' character in for loop.")
         }
 
         let end = self.parse_expr()?;
@@ -561,22 +1735,273 @@ impl<'a> Parser<'a> {
         // eat 'in' token
         match self.current()? {
             In => self.advance()?,
-            _ => return Err("Expected 'in' keyword in for loop.".to_owned())
+            _ => return self.error("Expected 'in' keyword in for loop.")
         }
 
         let body = self.parse_expr()?;
 
-        Ok(Expr::For {
+        Ok(self.expr(ExprKind::For {
             var_name: name,
             start: Box::new(start),
             end: Box::new(end),
             step: step.map(Box::new),
             body: Box::new(body)
-        })
+        }, span))
+    }
+
+    /// Returns whether the current token is part of the scrutinee of a `match`, outside of any delimiter,
+    /// in which case 'Name {' opens the arms of the `match` rather than a struct literal.
+    fn in_scrutinee(&self) -> bool {
+        let start = match self.scrutinee {
+            Some(start) => start,
+            None => return false
+        };
+
+        let depth = self.tokens[start..self.pos].iter().fold(0, |depth, token| match token.node {
+            LParen | LBracket | LBrace => depth + 1,
+            RParen | RBracket | RBrace => depth - 1,
+            _ => depth
+        });
+
+        depth == 0
+    }
+
+    /// Parses a `match` expression.
+    fn parse_match_expr(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span();
+
+        // eat 'match' token
+        self.advance()?;
+
+        let outer = self.scrutinee.replace(self.pos);
+        let expr = self.parse_expr();
+
+        self.scrutinee = outer;
+
+        let expr = expr?;
+        let open = self.span();
+
+        self.eat(LBrace)?;
+
+        let mut arms = vec![];
+
+        while self.current().map_err(|err| err.with_label(open, "unclosed 'match'"))? != RBrace {
+            let pattern = self.parse_pattern()?;
+
+            let guard = match self.curr() {
+                If => {
+                    self.advance()?;
+
+                    Some(self.parse_expr()?)
+                },
+
+                _ => None
+            };
+
+            self.eat(FatArrow)?;
+
+            let body = self.parse_expr()?;
+            let ends_with_block = self.tokens[self.pos - 1].node == RBrace;
+
+            arms.push(Arm { pattern, guard, body });
+
+            // Arms are separated by commas, except after a block
+            match self.current()? {
+                Comma => self.advance()?,
+                RBrace => (),
+                _ if ends_with_block => (),
+                _ => return self.error("Expected ',' or '}' character after 'match' arm.")
+                                .map_err(|err| err.with_label(open, "arms start here"))
+            }
+        }
+
+        self.advance();
+
+        Ok(self.expr(ExprKind::Match { expr: Box::new(expr), arms }, start))
+    }
+
+    /// Parses a pattern.
+    fn parse_pattern(&mut self) -> Result<Pattern, Diagnostic> {
+        let start = self.span();
+
+        let kind = match self.current()? {
+            Ident(ref name) if name == "_" => {
+                self.advance()?;
+
+                PatternKind::Wildcard
+            },
+
+            Ident(name) => {
+                self.advance()?;
+
+                match self.curr() {
+                    LParen => {
+                        self.advance()?;
+
+                        PatternKind::TupleStruct { name, fields: self.parse_pattern_list(start)? }
+                    },
+
+                    LBrace => self.parse_struct_pattern(name)?,
+
+                    _ => PatternKind::Binding(name)
+                }
+            },
+
+            Bool(b) => {
+                self.advance()?;
+
+                PatternKind::Bool(b)
+            },
+
+            Int(nb, suffix) => {
+                self.advance()?;
+                self.check_int(nb.into(), &suffix, start)?;
+
+                PatternKind::Int(nb.into(), suffix)
+            },
+
+            Float(nb) => {
+                self.advance()?;
+
+                PatternKind::Float(nb)
+            },
+
+            Op(ref op) if op == "-" => {
+                self.advance()?;
+
+                match self.curr() {
+                    Int(nb, suffix) => {
+                        self.advance()?;
+                        self.check_int(-i128::from(nb), &suffix, start)?;
+
+                        PatternKind::Int(-i128::from(nb), suffix)
+                    },
+
+                    Float(nb) => {
+                        self.advance()?;
+
+                        PatternKind::Float(-nb)
+                    },
+
+                    _ => return self.error("Expected number literal after '-' in pattern.")
+                }
+            },
+
+            Char(ch) => {
+                self.advance()?;
+
+                PatternKind::Char(ch)
+            },
+
+            Str(s) => {
+                self.advance()?;
+
+                PatternKind::Str(s)
+            },
+
+            LParen => {
+                self.advance()?;
+
+                let mut patterns = self.parse_pattern_list(start)?;
+
+                // '(p)' is a parenthesized pattern, while '(p,)' is a 1-tuple
+                let trailing_comma = self.tokens[self.pos - 2].node == Comma;
+
+                if patterns.len() == 1 && !trailing_comma {
+                    return Ok(patterns.remove(0));
+                }
+
+                PatternKind::Tuple(patterns)
+            },
+
+            _ => return self.error("Expected pattern.")
+        };
+
+        Ok(Pattern { kind, span: self.span_from(start) })
+    }
+
+    /// Parses comma-separated patterns until a ')', after the opening '('.
+    fn parse_pattern_list(&mut self, start: Span) -> Result<Vec<Pattern>, Diagnostic> {
+        let mut patterns = vec![];
+
+        while self.current()? != RParen {
+            patterns.push(self.parse_pattern()?);
+
+            match self.current()? {
+                Comma => self.advance()?,
+                RParen => (),
+                _ => return self.error("Expected ',' or ')' character in pattern.")
+                                .map_err(|err| err.with_label(start, "unclosed delimiter"))
+            }
+        }
+
+        self.advance()?;
+
+        Ok(patterns)
+    }
+
+    /// Parses the fields of a struct pattern, whose name has already been parsed.
+    fn parse_struct_pattern(&mut self, name: String) -> Result<PatternKind, Diagnostic> {
+        let open = self.span();
+
+        // eat '{' token
+        self.advance()?;
+
+        let mut fields = vec![];
+        let mut rest = false;
+
+        while self.current()? != RBrace {
+            if self.curr() == Dot {
+                self.advance()?;
+                self.eat(Dot)?;
+
+                rest = true;
+
+                if self.current()? != RBrace {
+                    return self.error("Expected '}' character after '..' in pattern.");
+                }
+
+                break;
+            }
+
+            let field_span = self.span();
+
+            let field = match self.curr() {
+                Ident(field) => field,
+                _ => return self.error("Expected field name in struct pattern.")
+            };
+
+            self.advance()?;
+
+            // 'Name { field }' is a shorthand for 'Name { field: field }'
+            let pattern = match self.curr() {
+                Colon => {
+                    self.advance()?;
+                    self.parse_pattern()?
+                },
+
+                _ => Pattern { kind: PatternKind::Binding(field.clone()), span: field_span }
+            };
+
+            fields.push((field, pattern));
+
+            match self.current()? {
+                Comma => self.advance()?,
+                RBrace => (),
+                _ => return self.error("Expected ',' or '}' character in struct pattern.")
+                                .map_err(|err| err.with_label(open, "fields start here"))
+            }
+        }
+
+        self.advance()?;
+
+        Ok(PatternKind::Struct { name, fields, rest })
     }
 
     /// Parses a var..in expression.
-    fn parse_var_expr(&mut self) -> Result<Expr, String> {
+    fn parse_var_expr(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span();
+
         // eat 'var' token
         self.advance()?;
 
@@ -586,14 +2011,14 @@ impl<'a> Parser<'a> {
         loop {
             let name = match self.curr() {
                 Ident(name) => name,
-                _ => return Err("Expected identifier in 'var..in' declaration.".to_owned())
+                _ => return self.error("Expected identifier in 'var..in' declaration.")
             };
 
             self.advance()?;
 
             // read (optional) initializer
             let initializer = match self.curr() {
-                Op('=') => Some({
+                Op(ref op) if op == "=" => Some({
                     self.advance()?;
                     self.parse_expr()?
                 }),
@@ -612,7 +2037,7 @@ impl<'a> Parser<'a> {
                     break;
                 }
                 _ => {
-                    return Err("Expected comma or 'in' keyword in variable declaration.".to_owned())
+                    return self.error("Expected comma or 'in' keyword in variable declaration.")
                 }
             }
         }
@@ -620,38 +2045,44 @@ impl<'a> Parser<'a> {
         // parse body
         let body = self.parse_expr()?;
 
-        Ok(Expr::VarIn {
-            variables: variables,
+        Ok(self.expr(ExprKind::VarIn {
+            variables,
             body: Box::new(body)
-        })
+        }, start))
     }
 
     /// Parses a primary expression (an identifier, a number or a parenthesized expression).
-    fn parse_primary(&mut self) -> Result<Expr, String> {
+    fn parse_primary(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span();
+
         match self.curr() {
             Ident(_) => self.parse_id_expr(),
-            Str(e) => { self.advance()?; Ok(Expr::Str(e)) },
-            Number(_) => self.parse_nb_expr(),
+            Str(e) => { self.advance(); Ok(self.expr(ExprKind::Str(e), start)) },
+            Char(ch) => { self.advance(); Ok(self.expr(ExprKind::Char(ch), start)) },
+            Bool(b) => { self.advance(); Ok(self.expr(ExprKind::Bool(b), start)) },
+            Int(..) | Float(_) => self.parse_nb_expr(),
             LParen => self.parse_paren_expr(),
+            LBracket => self.parse_list_expr(),
+            LBrace => self.parse_block(),
             If => self.parse_conditional_expr(),
             For => self.parse_for_expr(),
+            Match => self.parse_match_expr(),
             Var => self.parse_var_expr(),
-            _ => Err("Unknown expression.".to_owned())
+            _ => self.error("Unknown expression.")
         }
     }
 
     /// Parses a top-level expression and makes an anonymous function out of it,
     /// for easier compilation.
-    fn parse_toplevel_expr(&mut self) -> Result<Function, String> {
+    fn parse_toplevel_expr(&mut self) -> Result<Function, Diagnostic> {
         match self.parse_expr() {
             Ok(expr) => {
                 Ok(Function {
                     prototype: Prototype {
                         name: ANONYMOUS_FUNCTION_NAME.to_string(),
                         args: vec![],
-                        is_op: false,
-                        prec: 0
-
+                        ret: None,
+                        span: expr.span
                     },
                     body: Some(expr),
                     is_anon: true,
@@ -663,3 +2094,84 @@ impl<'a> Parser<'a> {
         }
     }
 }
+
+/// Wraps an expression so that the thunk it evaluates to gets forced.
+fn motivate(expr: Expr, span: Span) -> Expr {
+    let kind = match expr.kind {
+        ExprKind::Call { fn_name, args, bang: false } => ExprKind::Call { fn_name, args, bang: true },
+        ExprKind::Apply { callee, args, bang: false } => ExprKind::Apply { callee, args, bang: true },
+        ExprKind::MethodCall { receiver, method, args, bang: false } => {
+            ExprKind::MethodCall { receiver, method, args, bang: true }
+        },
+        kind => ExprKind::Mot(Box::new(Expr { kind, span: expr.span }))
+    };
+
+    Expr { kind, span }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a top-level expression.
+    fn parse_expr(source: &str) -> Expr {
+        match Parser::new(source.to_owned()).parse() {
+            Ok(Item::Function(Function { body: Some(expr), is_anon: true, .. })) => expr,
+            result => panic!("expected an expression, found {:?}", result)
+        }
+    }
+
+    #[test]
+    fn examples_parse() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        let mut count = 0;
+
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let source = std::fs::read_to_string(&path).unwrap();
+
+            if let Err(err) = Parser::new(source).parse_program() {
+                panic!("{} does not parse: {}", path.display(), err);
+            }
+
+            count += 1;
+        }
+
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn braces_after_a_scrutinee_open_the_arms() {
+        match parse_expr("match x { }").kind {
+            ExprKind::Match { expr, arms } => {
+                assert!(matches!(expr.kind, ExprKind::Variable(ref name) if name == "x"));
+                assert!(arms.is_empty());
+            },
+            kind => panic!("expected a match, found {:?}", kind)
+        }
+    }
+
+    #[test]
+    fn struct_literals_can_be_parenthesized_in_a_scrutinee() {
+        match parse_expr("match (P { x: 1 }) { _ => 2 }").kind {
+            ExprKind::Match { expr, arms } => {
+                assert!(matches!(expr.kind, ExprKind::StructLit { .. }));
+                assert_eq!(arms.len(), 1);
+            },
+            kind => panic!("expected a match, found {:?}", kind)
+        }
+
+        assert!(matches!(parse_expr("P { x: match y { _ => 1 } }").kind, ExprKind::StructLit { .. }));
+    }
+
+    #[test]
+    fn minus_is_folded_into_integer_literals() {
+        assert!(matches!(parse_expr("-9223372036854775808i64").kind,
+                         ExprKind::Int(nb, Some(ref ty)) if nb == i64::MIN.into() && ty == "i64"));
+        assert!(matches!(parse_expr("-5.abs()").kind, ExprKind::Call { ref fn_name, .. } if fn_name == "unary-"));
+
+        for source in &["9223372036854775808i64", "-1u8", "match x { -1u8 => 1 }"] {
+            assert!(Parser::new(source.to_string()).parse().is_err(), "{} parses", source);
+        }
+    }
+}
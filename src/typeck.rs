@@ -0,0 +1,1337 @@
+use crate::diag::{Diagnostic, EAGER_VALUE, TYPE_ERROR};
+use crate::lex::Span;
+use crate::parse::{Expr, ExprKind, Fields, Function, Member, Operators, Pattern, PatternKind, Prototype, Stmt, Struct, Type};
+use crate::num::NUMERIC_TYPES;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Defines the definitions that the static analyses of an item can refer to,
+/// including those of the program it belongs to that are not registered yet.
+pub struct Context<'a> {
+    pub structs: HashMap<&'a str, &'a Struct>,
+    pub functions: HashMap<&'a str, &'a Prototype>,
+    /// The names of the native functions, including the ones imported with `ext`.
+    pub builtins: HashSet<&'a str>,
+}
+
+/// Defines the type of an expression, as far as the checker can tell.
+#[derive(Debug, Clone, PartialEq)]
+enum Ty {
+    /// A type that cannot be known statically, which fits every type.
+    Unknown,
+    Bool,
+    Char,
+    Str,
+    /// A number of the named numeric type, such as `u64`.
+    Num(String),
+    /// An integer literal, which fits every numeric type.
+    IntLit,
+    Struct(String),
+    /// A thunk, which must be forced with `mot` or `!` wherever its value is demanded.
+    Ref(Box<Ty>),
+    List(Box<Ty>),
+    Map(Box<Ty>, Box<Ty>),
+    /// A tuple; the empty tuple is the unit type.
+    Tuple(Vec<Ty>),
+    Fn(Vec<Ty>, Box<Ty>),
+    /// A type variable, which is inferred from the way its values are used.
+    Var(usize),
+}
+
+impl Ty {
+    fn unit() -> Ty {
+        Ty::Tuple(vec![])
+    }
+
+    /// Returns whether the type may be numeric, given that its variables have been resolved.
+    fn is_numeric(&self) -> bool {
+        matches!(self, Ty::Num(_) | Ty::IntLit | Ty::Unknown | Ty::Var(_))
+    }
+
+    /// Returns the type as it can be written in a declaration, where integer literals are `i64`,
+    /// or `None` if it is not fully known.
+    fn declarable(&self) -> Option<Ty> {
+        let all = |types: &[Ty]| types.iter().map(Ty::declarable).collect::<Option<Vec<_>>>();
+
+        Some(match self {
+            Ty::Unknown | Ty::Var(_) => return None,
+            Ty::IntLit => Ty::Num("i64".to_owned()),
+            Ty::Ref(ty) => Ty::Ref(Box::new(ty.declarable()?)),
+            Ty::List(ty) => Ty::List(Box::new(ty.declarable()?)),
+            Ty::Map(key, value) => Ty::Map(Box::new(key.declarable()?), Box::new(value.declarable()?)),
+            Ty::Tuple(types) => Ty::Tuple(all(types)?),
+            Ty::Fn(params, ret) => Ty::Fn(all(params)?, Box::new(ret.declarable()?)),
+            ty => ty.clone()
+        })
+    }
+
+    /// Collects the type variables of the type, in the order they appear.
+    fn vars(&self, vars: &mut Vec<usize>) {
+        match self {
+            Ty::Var(var) if !vars.contains(var) => vars.push(*var),
+            Ty::Ref(ty) | Ty::List(ty) => ty.vars(vars),
+            Ty::Map(key, value) => {
+                key.vars(vars);
+                value.vars(vars);
+            },
+            Ty::Tuple(types) => types.iter().for_each(|ty| ty.vars(vars)),
+            Ty::Fn(params, ret) => {
+                params.iter().for_each(|ty| ty.vars(vars));
+                ret.vars(vars);
+            },
+            _ => ()
+        }
+    }
+
+    /// Writes the type, naming its type variables `'a`, `'b`, ... in the order of `vars`.
+    fn write(&self, f: &mut fmt::Formatter, vars: &[usize]) -> fmt::Result {
+        let list = |f: &mut fmt::Formatter, types: &[Ty]| {
+            for (i, ty) in types.iter().enumerate() {
+                write!(f, "{}", if i == 0 { "" } else { ", " })?;
+                ty.write(f, vars)?;
+            }
+
+            Ok(())
+        };
+
+        match self {
+            Ty::Unknown => write!(f, "_"),
+            Ty::Var(var) => match vars.iter().position(|v| v == var) {
+                Some(i) if i < 26 => write!(f, "'{}", (b'a' + i as u8) as char),
+                Some(i) => write!(f, "'t{}", i),
+                None => write!(f, "_")
+            },
+            Ty::Bool => write!(f, "bool"),
+            Ty::Char => write!(f, "char"),
+            Ty::Str => write!(f, "str"),
+            Ty::Num(name) => write!(f, "{}", name),
+            Ty::IntLit => write!(f, "{{integer}}"),
+            Ty::Struct(name) => write!(f, "{}", name),
+            Ty::Ref(ty) => {
+                write!(f, "&")?;
+                ty.write(f, vars)
+            },
+            Ty::List(ty) => {
+                write!(f, "[")?;
+                ty.write(f, vars)?;
+                write!(f, "]")
+            },
+            Ty::Map(key, value) => {
+                write!(f, "{{")?;
+                key.write(f, vars)?;
+                write!(f, " => ")?;
+                value.write(f, vars)?;
+                write!(f, "}}")
+            },
+            Ty::Tuple(types) => {
+                write!(f, "(")?;
+                list(f, types)?;
+                write!(f, "{})", if types.len() == 1 { "," } else { "" })
+            },
+            Ty::Fn(params, ret) => {
+                write!(f, "fn(")?;
+                list(f, params)?;
+                write!(f, ") -> ")?;
+                ret.write(f, vars)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut vars = vec![];
+
+        self.vars(&mut vars);
+        self.write(f, &vars)
+    }
+}
+
+/// Returns the number of arguments a native function expects, or `None` if it accepts
+/// any number of them, along with its parameters and return type as they are written in help messages.
+fn builtin_signature(name: &str) -> Option<(Option<usize>, &'static str)> {
+    let signature = match name {
+        "print," | "println," => (None, "(format: str, args...)"),
+        "getline" => (Some(0), "() -> str"),
+        "sqrt" | "sin" | "cos" => (Some(1), "(x: number) -> f64"),
+        "unary-" => (Some(1), "(x: number) -> number"),
+        "unary!" => (Some(1), "(x: bool | number) -> bool"),
+        "u8" => (Some(1), "(x: number) -> u8"),
+        "u64" => (Some(1), "(x: number) -> u64"),
+        "i64" => (Some(1), "(x: number) -> i64"),
+        "f64" => (Some(1), "(x: number) -> f64"),
+        "len" => (Some(1), "(xs: [T] | {K => V} | str) -> u64"),
+        "push" => (Some(2), "(xs: [T], x: T)"),
+        "pop" => (Some(1), "(xs: [T]) -> T"),
+        "contains" => (Some(2), "(xs: [T] | {T => V} | str, x: T | str | char) -> bool"),
+        "insert" => (Some(3), "(map: {K => V}, key: K, value: V)"),
+        "remove" => (Some(2), "(map: {K => V}, key: K) -> V"),
+        "keys" => (Some(1), "(map: {K => V}) -> [K]"),
+        "values" => (Some(1), "(map: {K => V}) -> [V]"),
+        "entries" => (Some(1), "(map: {K => V}) -> [(K, V)]"),
+        _ => return None
+    };
+
+    Some(signature)
+}
+
+/// Creates a type error.
+fn error(msg: impl Into<String>, span: Span) -> Diagnostic {
+    Diagnostic::error(msg, span).with_code(TYPE_ERROR)
+}
+
+/// Checks that the types of the fields of a struct exist.
+pub fn check_struct(def: &Struct, context: &Context) -> Vec<Diagnostic> {
+    let mut checker = Checker::new(context);
+
+    match def.fields {
+        Fields::Named(ref fields) => fields.iter().for_each(|(_, ty)| { checker.declared(ty, def.span); }),
+        Fields::Tuple(ref types) => types.iter().for_each(|ty| { checker.declared(ty, def.span); }),
+    }
+
+    checker.diagnostics
+}
+
+/// Checks that the body of a function fits its signature, and that the operands
+/// of its operators, the arguments of its calls and the branches of its
+/// conditionals and matches have compatible types.
+pub fn check_function(function: &Function, context: &Context) -> Vec<Diagnostic> {
+    let mut checker = Checker::new(context);
+    let prototype = &function.prototype;
+
+    let params = prototype.args
+                          .iter()
+                          .map(|(name, ty)| (name.clone(), checker.declared(ty, prototype.span)))
+                          .collect::<Vec<_>>();
+    let ret = prototype.ret.as_ref().map(|ty| checker.declared(ty, prototype.span));
+
+    if let Some(ref body) = function.body {
+        checker.scopes.push(HashMap::new());
+
+        for (name, ty) in params {
+            checker.bind(&name, ty);
+        }
+
+        // The body is forced when the function is called, so it can result in a thunk
+        let ty = checker.check(body);
+        let ty = checker.forced(ty);
+
+        if let Some(ret) = ret {
+            let value = checker.forced(ret.clone());
+
+            if !checker.fits(&value, &ty) {
+                // Point at the value the body results in, rather than at the whole body
+                let span = match body.kind {
+                    ExprKind::Block { tail: Some(ref tail), .. } => tail.span,
+                    _ => body.span
+                };
+
+                let ty = checker.solved(&ty);
+
+                checker.report(error(format!("Function '{}' returns '{}' but its body has type '{}'.", prototype.name, ret, ty), span)
+                    .with_label(prototype.span, "return type declared here"));
+            }
+        }
+    }
+
+    checker.diagnostics
+}
+
+/// Infers the type of an expression, such as a REPL line, or returns the type errors it contains.
+pub fn type_of(expr: &Expr, context: &Context) -> Result<String, Vec<Diagnostic>> {
+    let mut checker = Checker::new(context);
+
+    checker.scopes.push(HashMap::new());
+
+    let ty = checker.check(expr);
+
+    if checker.diagnostics.is_empty() {
+        Ok(checker.solved(&ty).to_string())
+    } else {
+        Err(checker.diagnostics)
+    }
+}
+
+/// Represents the type of a variable, which is generic over the type variables `vars`.
+///
+/// Only closures bound by 'let' are generic, since the other values, such as lists, may be mutated.
+#[derive(Clone)]
+struct Scheme {
+    vars: Vec<usize>,
+    ty: Ty,
+}
+
+/// Represents the type checker of an item, along with the diagnostics it reported.
+struct Checker<'a> {
+    context: &'a Context<'a>,
+    /// The types of the variables in scope, innermost last.
+    scopes: Vec<HashMap<String, Scheme>>,
+    /// The solutions of the type variables, indexed by variable.
+    vars: Vec<Option<Ty>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(context: &'a Context<'a>) -> Checker<'a> {
+        Checker { context, scopes: vec![], vars: vec![], diagnostics: vec![] }
+    }
+
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Returns a new type variable.
+    fn fresh(&mut self) -> Ty {
+        self.vars.push(None);
+
+        Ty::Var(self.vars.len() - 1)
+    }
+
+    /// Replaces a solved type variable by its solution, leaving the types it contains as they are.
+    fn shallow(&self, ty: &Ty) -> Ty {
+        match ty {
+            Ty::Var(var) => match self.vars[*var] {
+                Some(ref solution) => self.shallow(solution),
+                None => ty.clone()
+            },
+            ty => ty.clone()
+        }
+    }
+
+    /// Replaces every solved type variable of a type by its solution.
+    fn solved(&self, ty: &Ty) -> Ty {
+        match self.shallow(ty) {
+            Ty::Ref(ty) => Ty::Ref(Box::new(self.solved(&ty))),
+            Ty::List(ty) => Ty::List(Box::new(self.solved(&ty))),
+            Ty::Map(key, value) => Ty::Map(Box::new(self.solved(&key)), Box::new(self.solved(&value))),
+            Ty::Tuple(types) => Ty::Tuple(types.iter().map(|ty| self.solved(ty)).collect()),
+            Ty::Fn(params, ret) => Ty::Fn(params.iter().map(|ty| self.solved(ty)).collect(), Box::new(self.solved(&ret))),
+            ty => ty
+        }
+    }
+
+    /// Returns the type of the value a thunk of type `ty` computes.
+    fn forced(&self, ty: Ty) -> Ty {
+        match self.shallow(&ty) {
+            Ty::Ref(ty) => self.forced(*ty),
+            ty => ty
+        }
+    }
+
+    /// Returns whether the type variable `var` occurs in `ty`.
+    fn occurs(&self, var: usize, ty: &Ty) -> bool {
+        match self.shallow(ty) {
+            Ty::Var(other) => other == var,
+            Ty::Ref(ty) | Ty::List(ty) => self.occurs(var, &ty),
+            Ty::Map(key, value) => self.occurs(var, &key) || self.occurs(var, &value),
+            Ty::Tuple(types) => types.iter().any(|ty| self.occurs(var, ty)),
+            Ty::Fn(params, ret) => params.iter().any(|ty| self.occurs(var, ty)) || self.occurs(var, &ret),
+            _ => false
+        }
+    }
+
+    /// Returns the most precise type that fits both `a` and `b` if they are compatible,
+    /// solving the type variables they contain along the way.
+    fn unify(&mut self, a: &Ty, b: &Ty) -> Option<Ty> {
+        let ty = match (a, b) {
+            (Ty::Unknown, ty) | (ty, Ty::Unknown) => ty.clone(),
+            (Ty::Var(a), Ty::Var(b)) if a == b => Ty::Var(*a),
+            (Ty::Var(var), ty) | (ty, Ty::Var(var)) => {
+                // A solution is refined as more is known, like a number literal that turns out to be a 'u64'
+                let solution = match self.vars[*var].clone() {
+                    Some(solution) => self.unify(&solution, ty)?,
+                    None => ty.clone()
+                };
+
+                match self.shallow(&solution) {
+                    Ty::Var(other) if other == *var => (),
+                    _ if self.occurs(*var, &solution) => return None,
+                    _ => self.vars[*var] = Some(solution)
+                }
+
+                Ty::Var(*var)
+            },
+            (Ty::Ref(a), Ty::Ref(b)) => Ty::Ref(Box::new(self.unify(a, b)?)),
+            (Ty::IntLit, Ty::Num(name)) | (Ty::Num(name), Ty::IntLit) => Ty::Num(name.clone()),
+            (Ty::Num(a), Ty::Num(b)) if a == b => Ty::Num(a.clone()),
+            (Ty::Struct(a), Ty::Struct(b)) if a == b => Ty::Struct(a.clone()),
+            (Ty::List(a), Ty::List(b)) => Ty::List(Box::new(self.unify(a, b)?)),
+            (Ty::Map(ak, av), Ty::Map(bk, bv)) => Ty::Map(Box::new(self.unify(ak, bk)?), Box::new(self.unify(av, bv)?)),
+            (Ty::Tuple(a), Ty::Tuple(b)) if a.len() == b.len() => {
+                Ty::Tuple(a.iter().zip(b).map(|(a, b)| self.unify(a, b)).collect::<Option<_>>()?)
+            },
+            (Ty::Fn(ap, ar), Ty::Fn(bp, br)) if ap.len() == bp.len() => {
+                let params = ap.iter().zip(bp).map(|(a, b)| self.unify(a, b)).collect::<Option<_>>()?;
+
+                Ty::Fn(params, Box::new(self.unify(ar, br)?))
+            },
+            (a, b) if a == b => a.clone(),
+            _ => return None
+        };
+
+        Some(ty)
+    }
+
+    /// Returns whether a value of type `actual` can be used where `expected` is.
+    fn fits(&mut self, expected: &Ty, actual: &Ty) -> bool {
+        self.unify(expected, actual).is_some()
+    }
+
+    /// Returns the type of a thunk that computes a value of type `ty`; forcing a thunk forces the thunks it returns.
+    fn thunk(&self, ty: Ty) -> Ty {
+        match self.shallow(&ty) {
+            Ty::Ref(_) | Ty::Unknown => ty,
+            _ => Ty::Ref(Box::new(ty))
+        }
+    }
+
+    /// Reports that a value of type `actual` does not fit `expected`, telling
+    /// apart the thunks that must be forced and the values that should be delayed.
+    fn report_mismatch(&mut self, expected: &Ty, actual: &Ty, span: Span) {
+        let (expected_ty, actual_ty) = (self.shallow(expected), self.shallow(actual));
+
+        match (&expected_ty, &actual_ty) {
+            (Ty::Ref(_), Ty::Ref(_)) => (),
+            (_, Ty::Ref(value)) if self.fits(expected, value) => {
+                return self.report(self.thunk_error(actual, span));
+            },
+            (Ty::Ref(value), _) if self.fits(value, actual) => {
+                return self.report(Diagnostic::warning(format!("Expected the thunk '{}' but found a value of type '{}', which is computed eagerly.",
+                                                               self.solved(expected), self.solved(actual)), span)
+                    .with_code(EAGER_VALUE)
+                    .with_help("use '&expr' to delay it"));
+            },
+            _ => ()
+        }
+
+        self.report(error(format!("Mismatched types: expected '{}' but found '{}'.", self.solved(expected), self.solved(actual)), span));
+    }
+
+    fn thunk_error(&self, ty: &Ty, span: Span) -> Diagnostic {
+        error(format!("Expected a value but found the thunk '{}'.", self.solved(ty)), span)
+            .with_help("force it with 'mot expr' or 'expr!'")
+    }
+
+    /// Checks an expression whose value is demanded, reporting an error if it is a thunk that is not forced.
+    fn check_value(&mut self, expr: &Expr) -> Ty {
+        let ty = self.check(expr);
+
+        self.demand(ty, expr.span)
+    }
+
+    /// Returns the type of a value demanded at `span`, reporting an error if it is a thunk.
+    fn demand(&mut self, ty: Ty, span: Span) -> Ty {
+        if let Ty::Ref(_) = self.shallow(&ty) {
+            self.report(self.thunk_error(&ty, span));
+        }
+
+        self.forced(ty)
+    }
+
+    /// Resolves a type annotation, collecting the names it uses that are not types.
+    fn resolve(&self, ty: &Type, unknown: &mut Vec<String>) -> Ty {
+        match ty {
+            Type::Named(name) => match name.as_str() {
+                "bool" => Ty::Bool,
+                "char" => Ty::Char,
+                "str" => Ty::Str,
+                name if NUMERIC_TYPES.contains(&name) => Ty::Num(name.to_owned()),
+                name if self.context.structs.contains_key(name) => Ty::Struct(name.to_owned()),
+                name => {
+                    unknown.push(name.to_owned());
+
+                    Ty::Unknown
+                }
+            },
+            Type::Ref(ty) => self.thunk(self.resolve(ty, unknown)),
+            Type::Slice(ty) => Ty::List(Box::new(self.resolve(ty, unknown))),
+            Type::Map(key, value) => Ty::Map(Box::new(self.resolve(key, unknown)), Box::new(self.resolve(value, unknown))),
+            Type::Tuple(types) => Ty::Tuple(types.iter().map(|ty| self.resolve(ty, unknown)).collect()),
+            Type::Fn { params, ret } => {
+                Ty::Fn(params.iter().map(|ty| self.resolve(ty, unknown)).collect(), Box::new(self.resolve(ret, unknown)))
+            }
+        }
+    }
+
+    /// Resolves a type annotation found at `span`, reporting the names it uses that are not types.
+    fn declared(&mut self, ty: &Type, span: Span) -> Ty {
+        let mut unknown = vec![];
+        let ty = self.resolve(ty, &mut unknown);
+
+        for name in unknown {
+            self.report(error(format!("Unknown type '{}'.", name), span)
+                .with_help(format!("the builtin types are bool, char, str and {}", NUMERIC_TYPES.join(", "))));
+        }
+
+        ty
+    }
+
+    /// Resolves a type annotation of another definition, whose errors are reported when it is checked.
+    fn signature(&self, ty: &Type) -> Ty {
+        self.resolve(ty, &mut vec![])
+    }
+
+    /// Returns the type of a variable, giving fresh type variables to each use of a generic one.
+    fn lookup(&mut self, name: &str) -> Option<Ty> {
+        let scheme = self.scopes.iter().rev().find_map(|scope| scope.get(name)).cloned()?;
+        let vars = scheme.vars.iter().map(|&var| (var, self.fresh())).collect::<HashMap<_, _>>();
+
+        Some(self.substitute(&scheme.ty, &vars))
+    }
+
+    fn bind(&mut self, name: &str, ty: Ty) {
+        self.bind_scheme(name, Scheme { vars: vec![], ty });
+    }
+
+    /// Binds a variable that is generic over the type variables that only its type contains.
+    fn bind_generic(&mut self, name: &str, ty: Ty) {
+        let mut vars = vec![];
+        let mut bound = vec![];
+
+        self.free_vars(&ty, &mut vars);
+
+        for scheme in self.scopes.iter().flat_map(HashMap::values) {
+            self.free_vars(&scheme.ty, &mut bound);
+        }
+
+        vars.retain(|var| !bound.contains(var));
+
+        self.bind_scheme(name, Scheme { vars, ty: self.solved(&ty) });
+    }
+
+    fn bind_scheme(&mut self, name: &str, scheme: Scheme) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_owned(), scheme);
+        }
+    }
+
+    /// Collects the unsolved type variables of `ty` into `vars`.
+    fn free_vars(&self, ty: &Ty, vars: &mut Vec<usize>) {
+        match self.shallow(ty) {
+            Ty::Var(var) if !vars.contains(&var) => vars.push(var),
+            Ty::Ref(ty) | Ty::List(ty) => self.free_vars(&ty, vars),
+            Ty::Map(key, value) => {
+                self.free_vars(&key, vars);
+                self.free_vars(&value, vars);
+            },
+            Ty::Tuple(types) => types.iter().for_each(|ty| self.free_vars(ty, vars)),
+            Ty::Fn(params, ret) => {
+                params.iter().for_each(|ty| self.free_vars(ty, vars));
+                self.free_vars(&ret, vars);
+            },
+            _ => ()
+        }
+    }
+
+    /// Replaces the type variables of `ty` that are keys of `vars` by their values.
+    fn substitute(&self, ty: &Ty, vars: &HashMap<usize, Ty>) -> Ty {
+        match self.shallow(ty) {
+            Ty::Var(var) => vars.get(&var).cloned().unwrap_or(Ty::Var(var)),
+            Ty::Ref(ty) => Ty::Ref(Box::new(self.substitute(&ty, vars))),
+            Ty::List(ty) => Ty::List(Box::new(self.substitute(&ty, vars))),
+            Ty::Map(key, value) => Ty::Map(Box::new(self.substitute(&key, vars)), Box::new(self.substitute(&value, vars))),
+            Ty::Tuple(types) => Ty::Tuple(types.iter().map(|ty| self.substitute(ty, vars)).collect()),
+            Ty::Fn(params, ret) => {
+                Ty::Fn(params.iter().map(|ty| self.substitute(ty, vars)).collect(), Box::new(self.substitute(&ret, vars)))
+            },
+            ty => ty
+        }
+    }
+
+    /// Checks an expression, reporting an error if its type does not fit `expected`.
+    fn expect(&mut self, expected: &Ty, expr: &Expr) -> Ty {
+        let ty = self.check(expr);
+
+        if !self.fits(expected, &ty) {
+            self.report_mismatch(expected, &ty, expr.span);
+        }
+
+        ty
+    }
+
+    /// Returns the type of an expression, reporting the type errors it contains.
+    fn check(&mut self, expr: &Expr) -> Ty {
+        match &expr.kind {
+            ExprKind::Bool(_) => Ty::Bool,
+            ExprKind::Int(_, None) => Ty::IntLit,
+            ExprKind::Int(_, Some(suffix)) => Ty::Num(suffix.clone()),
+            ExprKind::Float(_) => Ty::Num("f64".to_owned()),
+            ExprKind::Char(_) => Ty::Char,
+            ExprKind::Str(_) => Ty::Str,
+
+            ExprKind::Variable(name) => match self.lookup(name) {
+                Some(ty) => ty,
+                None => {
+                    self.report(error(format!("Unknown variable '{}'.", name), expr.span));
+
+                    Ty::Unknown
+                }
+            },
+
+            ExprKind::Binary { op, left, right } if op == "=" => {
+                let expected = match left.kind {
+                    ExprKind::Variable(_) | ExprKind::Index { .. } => self.check(left),
+                    _ => Ty::Unknown
+                };
+
+                self.expect(&expected, right)
+            },
+
+            ExprKind::Binary { op, left, right } => {
+                let operands = [(self.check_value(left), left.span), (self.check_value(right), right.span)];
+
+                self.check_binary(op, &operands, expr.span)
+            },
+
+            ExprKind::Block { stmts, tail } => {
+                self.scopes.push(HashMap::new());
+
+                for stmt in stmts {
+                    match stmt {
+                        Stmt::Let { name, ty: Some(ty), value } => {
+                            let ty = self.declared(ty, value.span);
+
+                            self.expect(&ty, value);
+                            self.bind(name, ty);
+                        },
+
+                        Stmt::Let { name, ty: None, value } => {
+                            let ty = self.check(value);
+
+                            match value.kind {
+                                ExprKind::Closure { .. } => self.bind_generic(name, ty),
+                                _ => self.bind(name, ty)
+                            }
+                        },
+
+                        Stmt::Expr(expr) => { self.check(expr); }
+                    }
+                }
+
+                let ty = match tail {
+                    Some(tail) => self.check(tail),
+                    None => Ty::unit()
+                };
+
+                self.scopes.pop();
+
+                ty
+            },
+
+            ExprKind::Call { fn_name, args, bang } => {
+                let args = self.check_all(args);
+
+                // Variables shadow functions, like they do when evaluating the call
+                let ret = match self.lookup(fn_name) {
+                    Some(callee) => self.check_apply(callee, &args, expr.span),
+                    None => self.check_call(fn_name, &args, expr.span)
+                };
+
+                self.returned(ret, *bang)
+            },
+
+            ExprKind::Apply { callee, args, bang } => {
+                let callee = self.check(callee);
+                let args = self.check_all(args);
+                let ret = self.check_apply(callee, &args, expr.span);
+
+                self.returned(ret, *bang)
+            },
+
+            ExprKind::MethodCall { receiver, method, args, bang } => {
+                let mut all = vec![(self.check(receiver), receiver.span)];
+
+                all.extend(self.check_all(args));
+
+                let ret = self.check_call(method, &all, expr.span);
+
+                self.returned(ret, *bang)
+            },
+
+            // The parameters of closures are not annotated, and are inferred from the way the body uses them
+            ExprKind::Closure { params, body } => {
+                let types = params.iter().map(|_| self.fresh()).collect::<Vec<_>>();
+
+                self.scopes.push(HashMap::new());
+
+                for (param, ty) in params.iter().zip(&types) {
+                    self.bind(param, ty.clone());
+                }
+
+                // The body of a closure is forced when it is called, like the body of a function
+                let ret = self.check(body);
+                let ret = self.forced(ret);
+
+                self.scopes.pop();
+
+                Ty::Fn(types, Box::new(ret))
+            },
+
+            ExprKind::StructLit { name, fields } => self.check_struct_lit(name, fields, expr.span),
+
+            ExprKind::Tuple(elements) => Ty::Tuple(elements.iter().map(|element| {
+                let ty = self.check(element);
+
+                self.forced(ty)
+            }).collect()),
+
+            ExprKind::List(elements) => {
+                let element = self.check_agree(elements.iter(), "List elements");
+
+                Ty::List(Box::new(element))
+            },
+
+            ExprKind::Map(entries) => {
+                let key = self.check_agree(entries.iter().map(|(key, _)| key), "Map keys");
+                let value = self.check_agree(entries.iter().map(|(_, value)| value), "Map values");
+
+                Ty::Map(Box::new(key), Box::new(value))
+            },
+
+            ExprKind::Index { expr: target, index } => {
+                match self.check_value(target) {
+                    Ty::List(element) => {
+                        self.expect(&Ty::Num("u64".to_owned()), index);
+
+                        *element
+                    },
+                    Ty::Map(key, value) => {
+                        self.expect(&key, index);
+
+                        *value
+                    },
+                    // A collection whose type is not known yet is a list if it is indexed by a 'u64', and a map otherwise
+                    Ty::Var(var) => {
+                        let key = self.check_value(index);
+                        let value = self.fresh();
+
+                        let collection = match self.shallow(&key) {
+                            Ty::IntLit => Ty::List(Box::new(value.clone())),
+                            Ty::Num(ref ty) if ty == "u64" => Ty::List(Box::new(value.clone())),
+                            _ => Ty::Map(Box::new(key), Box::new(value.clone()))
+                        };
+
+                        self.unify(&Ty::Var(var), &collection);
+
+                        value
+                    },
+                    Ty::Unknown => {
+                        self.check(index);
+
+                        Ty::Unknown
+                    },
+                    ty => {
+                        self.check(index);
+                        self.report(error(format!("Expected a list or a map but found '{}'.", self.solved(&ty)), target.span));
+
+                        Ty::Unknown
+                    }
+                }
+            },
+
+            ExprKind::Field { expr: target, member } => {
+                let ty = self.check_value(target);
+
+                self.check_field(ty, member, target.span, expr.span)
+            },
+
+            ExprKind::Match { expr: scrutinee, arms } => {
+                let scrutinee = self.check_value(scrutinee);
+                let mut result: Option<(Ty, Span)> = None;
+
+                for arm in arms {
+                    self.scopes.push(HashMap::new());
+
+                    self.check_pattern(&arm.pattern, &scrutinee);
+
+                    if let Some(ref guard) = arm.guard {
+                        self.expect(&Ty::Bool, guard);
+                    }
+
+                    let ty = self.check(&arm.body);
+
+                    self.scopes.pop();
+
+                    result = match result {
+                        None => Some((ty, arm.body.span)),
+                        Some((expected, first)) => match self.unify(&expected, &ty) {
+                            Some(ty) => Some((ty, first)),
+                            None => {
+                                let (expected_name, ty) = (self.solved(&expected), self.solved(&ty));
+
+                                self.report(error(format!("Match arms have incompatible types: expected '{}' but found '{}'.", expected_name, ty), arm.body.span)
+                                    .with_label(first, "first arm is here"));
+
+                                Some((expected, first))
+                            }
+                        }
+                    };
+                }
+
+                result.map_or(Ty::Unknown, |(ty, _)| ty)
+            },
+
+            ExprKind::Lazy(expr) => {
+                let ty = self.check(expr);
+
+                self.thunk(ty)
+            },
+
+            ExprKind::Mot(expr) => {
+                let ty = self.check(expr);
+
+                self.forced(ty)
+            },
+
+            ExprKind::Conditional { cond, consequence, alternative } => {
+                self.expect(&Ty::Bool, cond);
+
+                let expected = self.check(consequence);
+                let ty = self.check(alternative);
+
+                match self.unify(&expected, &ty) {
+                    Some(ty) => ty,
+                    None => {
+                        let (expected_name, ty) = (self.solved(&expected), self.solved(&ty));
+
+                        self.report(error(format!("'if' and 'else' have incompatible types: expected '{}' but found '{}'.", expected_name, ty), alternative.span)
+                            .with_label(consequence.span, "'then' branch is here"));
+
+                        expected
+                    }
+                }
+            },
+
+            ExprKind::For { var_name, start, end, step, body } => {
+                let start = self.check_value(start);
+
+                if !start.is_numeric() {
+                    self.report(error(format!("Expected a number to start the loop but found '{}'.", self.solved(&start)), expr.span));
+                }
+
+                self.scopes.push(HashMap::new());
+                self.bind(var_name, start.clone());
+
+                self.expect(&Ty::Bool, end);
+
+                if let Some(step) = step {
+                    self.expect(&start, step);
+                }
+
+                self.check(body);
+
+                self.scopes.pop();
+
+                Ty::unit()
+            },
+
+            ExprKind::VarIn { variables, body } => {
+                self.scopes.push(HashMap::new());
+
+                for (name, initializer) in variables {
+                    let ty = match initializer {
+                        Some(init) => self.check(init),
+                        None => Ty::IntLit
+                    };
+
+                    self.bind(name, ty);
+                }
+
+                let ty = self.check(body);
+
+                self.scopes.pop();
+
+                ty
+            }
+        }
+    }
+
+    /// Checks the arguments of a call, which are passed as they are, be they thunks or values.
+    fn check_all(&mut self, args: &[Expr]) -> Vec<(Ty, Span)> {
+        args.iter().map(|arg| (self.check(arg), arg.span)).collect()
+    }
+
+    /// Returns the type of a call to a callee that returns `ret`, which is a thunk unless the call is forced with `!`.
+    fn returned(&self, ret: Ty, bang: bool) -> Ty {
+        if bang { self.forced(ret) } else { self.thunk(ret) }
+    }
+
+    /// Checks that the values of expressions, such as the elements of a list, have compatible types, and returns it.
+    fn check_agree<'e>(&mut self, exprs: impl Iterator<Item = &'e Expr>, what: &str) -> Ty {
+        // The elements of an empty list are inferred from the way it is used
+        let mut result = self.fresh();
+
+        for expr in exprs {
+            let ty = self.check_value(expr);
+
+            match self.unify(&result, &ty) {
+                Some(ty) => result = ty,
+                None => {
+                    let (expected, ty) = (self.solved(&result), self.solved(&ty));
+
+                    self.report(error(format!("{} have incompatible types: expected '{}' but found '{}'.", what, expected, ty), expr.span))
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Checks the arguments of a callee against its parameters.
+    fn check_args(&mut self, callee: &str, params: &[Ty], args: &[(Ty, Span)], span: Span, def_span: Option<Span>) {
+        if params.len() != args.len() {
+            let mut err = error(format!("{} expects {} argument(s) but {} were given.", callee, params.len(), args.len()), span);
+
+            if let Some(def_span) = def_span {
+                err = err.with_label(def_span, "defined here");
+            }
+
+            return self.report(err);
+        }
+
+        for (param, (arg, span)) in params.iter().zip(args) {
+            if !self.fits(param, arg) {
+                self.report_mismatch(param, arg, *span);
+            }
+        }
+    }
+
+    /// Checks a call to a closure, given its type.
+    fn check_apply(&mut self, callee: Ty, args: &[(Ty, Span)], span: Span) -> Ty {
+        match self.forced(callee) {
+            Ty::Fn(params, ret) => {
+                self.check_args("Closure", &params, args, span, None);
+
+                *ret
+            },
+            Ty::Unknown => Ty::Unknown,
+            // A callee of unknown type, such as a closure parameter, is inferred from its arguments
+            Ty::Var(var) => {
+                let ret = self.fresh();
+                let ty = Ty::Fn(args.iter().map(|(ty, _)| ty.clone()).collect(), Box::new(ret.clone()));
+
+                self.unify(&Ty::Var(var), &ty);
+
+                ret
+            },
+            ty => {
+                self.report(error(format!("Expected a closure but found '{}'.", self.solved(&ty)), span));
+
+                Ty::Unknown
+            }
+        }
+    }
+
+    /// Checks a call to the function `name`, which is resolved like when evaluating
+    /// it: user-defined functions first, then the constructors of tuple structs, then builtins.
+    fn check_call(&mut self, name: &str, args: &[(Ty, Span)], span: Span) -> Ty {
+        if let Some(prototype) = self.context.functions.get(name).copied() {
+            let params = prototype.args.iter().map(|(_, ty)| self.signature(ty)).collect::<Vec<_>>();
+
+            self.check_args(&format!("Function '{}'", name), &params, args, span, Some(prototype.span));
+
+            // The return type of a function is unknown unless it is annotated
+            return prototype.ret.as_ref().map_or(Ty::Unknown, |ty| self.signature(ty));
+        }
+
+        if let Some(def) = self.context.structs.get(name).copied() {
+            match def.fields {
+                Fields::Tuple(ref types) => {
+                    let params = types.iter().map(|ty| self.signature(ty)).collect::<Vec<_>>();
+
+                    self.check_args(&format!("Struct '{}'", name), &params, args, span, Some(def.span));
+                },
+
+                Fields::Named(_) => self.report(error(format!("Struct '{}' has named fields.", name), span)
+                    .with_label(def.span, "struct defined here")
+                    .with_help(format!("use '{} {{ field: value, ... }}' to build it", name)))
+            }
+
+            return Ty::Struct(name.to_owned());
+        }
+
+        self.check_builtin(name, args, span)
+    }
+
+    /// Checks a call to a native function against its signature, reporting the calls to functions that do not exist.
+    fn check_builtin(&mut self, name: &str, args: &[(Ty, Span)], span: Span) -> Ty {
+        // The arguments of native functions and builtin operators are demanded, and must not be thunks
+        let types = args.iter().map(|(ty, span)| self.demand(ty.clone(), *span)).collect::<Vec<_>>();
+
+        let (arity, signature) = match builtin_signature(name) {
+            Some(signature) if self.context.builtins.contains(name) => signature,
+            _ => {
+                let err = error(format!("Unknown function '{}'.", name), span);
+
+                // Native functions with a path, such as 'std::stdio::getline', must be imported
+                let err = match self.context.builtins.iter().find(|path| path.ends_with(&format!("::{}", name))) {
+                    Some(path) => err.with_help(format!("import it with 'ext {};'", path)),
+                    None => err
+                };
+
+                self.report(err);
+
+                return Ty::Unknown;
+            }
+        };
+
+        let signature = format!("fn {}{}", name, signature);
+
+        if arity.is_some_and(|arity| arity != args.len()) {
+            self.report(error(format!("Function '{}' expects {} argument(s) but {} were given.", name, arity.unwrap_or_default(), args.len()), span)
+                .with_help(format!("its signature is '{}'", signature)));
+
+            return Ty::Unknown;
+        }
+
+        // A collection whose type is not known yet is inferred from the function it is passed to
+        if let Some(Ty::Var(var)) = types.first().map(|ty| self.shallow(ty)) {
+            let collection = match name {
+                "push" | "pop" => Some(Ty::List(Box::new(self.fresh()))),
+                "insert" | "remove" | "keys" | "values" | "entries" => Some(Ty::Map(Box::new(self.fresh()), Box::new(self.fresh()))),
+                _ => None
+            };
+
+            if let Some(collection) = collection {
+                self.unify(&Ty::Var(var), &collection);
+            }
+        }
+
+        let types = types.iter().map(|ty| self.shallow(ty)).collect::<Vec<_>>();
+        let u64 = || Ty::Num("u64".to_owned());
+        let numeric = |ty: &Ty| ty.is_numeric() || matches!(ty, Ty::Var(_));
+
+        match (name, types.as_slice()) {
+            ("print," | "println,", _) => Ty::unit(),
+            ("getline", []) => Ty::Str,
+
+            ("sqrt" | "sin" | "cos", [ty]) if numeric(ty) => Ty::Num("f64".to_owned()),
+            ("unary-", [ty]) if numeric(ty) => ty.clone(),
+            ("u8" | "u64" | "i64" | "f64", [ty]) if numeric(ty) => Ty::Num(name.to_owned()),
+            ("unary!", [ty]) if numeric(ty) || *ty == Ty::Bool => Ty::Bool,
+
+            ("len", [Ty::List(_) | Ty::Map(..) | Ty::Str | Ty::Var(_)]) => u64(),
+
+            ("push", [Ty::List(element), ty]) => {
+                if !self.fits(element, ty) {
+                    self.report_mismatch(element, ty, args[1].1);
+                }
+
+                Ty::unit()
+            },
+            ("pop", [Ty::List(element)]) => (**element).clone(),
+
+            ("contains", [Ty::List(expected), ty] | [Ty::Map(expected, _), ty]) => {
+                if !self.fits(expected, ty) {
+                    self.report_mismatch(expected, ty, args[1].1);
+                }
+
+                Ty::Bool
+            },
+            ("contains", [Ty::Str, Ty::Str | Ty::Char] | [Ty::Var(_), _]) => Ty::Bool,
+
+            ("insert", [Ty::Map(key, value), k, v]) => {
+                if !self.fits(key, k) {
+                    self.report_mismatch(key, k, args[1].1);
+                }
+
+                if !self.fits(value, v) {
+                    self.report_mismatch(value, v, args[2].1);
+                }
+
+                Ty::unit()
+            },
+            ("remove", [Ty::Map(key, value), k]) => {
+                if !self.fits(key, k) {
+                    self.report_mismatch(key, k, args[1].1);
+                }
+
+                (**value).clone()
+            },
+            ("keys", [Ty::Map(key, _)]) => Ty::List(key.clone()),
+            ("values", [Ty::Map(_, value)]) => Ty::List(value.clone()),
+            ("entries", [Ty::Map(key, value)]) => Ty::List(Box::new(Ty::Tuple(vec![(**key).clone(), (**value).clone()]))),
+
+            // Values of unknown types are left to the evaluator
+            (_, types) if types.contains(&Ty::Unknown) => Ty::Unknown,
+
+            // Operators are applied to the operands they fit
+            ("unary-" | "unary!", [ty]) => {
+                self.report(error(format!("Operator '{}' cannot be applied to '{}'.", &name[5..], self.solved(ty)), span));
+
+                Ty::Unknown
+            },
+
+            (_, types) => {
+                let types = types.iter().map(|ty| format!("'{}'", self.solved(ty))).collect::<Vec<_>>();
+
+                self.report(error(format!("Function '{}' cannot be applied to {}.", name, types.join(", ")), span)
+                    .with_help(format!("its signature is '{}'", signature)));
+
+                Ty::Unknown
+            }
+        }
+    }
+
+    /// Checks a binary operator, given the forced types of its operands.
+    fn check_binary(&mut self, op: &str, operands: &[(Ty, Span); 2], span: Span) -> Ty {
+        let (left, right) = (&operands[0].0, &operands[1].0);
+        let both = self.unify(left, right).map(|ty| self.shallow(&ty));
+
+        let builtin = match (op, both) {
+            ("==" | "!=", Some(_)) => Some(Ty::Bool),
+
+            ("<" | ">" | "<=" | ">=", Some(ty)) if ty.is_numeric() || ty == Ty::Char || ty == Ty::Str => Some(Ty::Bool),
+
+            ("+", Some(Ty::Str)) => Some(Ty::Str),
+            ("+" | "-" | "*" | "/" | "%", Some(ty)) if ty.is_numeric() => Some(ty),
+
+            ("&" | "|" | "^" | "&&" | "||", Some(ty)) if self.fits(&Ty::Bool, &ty) => Some(Ty::Bool),
+
+            _ => None
+        };
+
+        if let Some(ty) = builtin {
+            return ty;
+        }
+
+        // Otherwise, a user-defined operator such as 'binary+' applies
+        let name = format!("binary{}", op);
+
+        if let Some(prototype) = self.context.functions.get(name.as_str()).copied() {
+            // The operands are evaluated before the operator is applied, and can then be passed as thunks
+            let operands = operands.iter().enumerate().map(|(i, (ty, span))| match prototype.args.get(i) {
+                Some((_, Type::Ref(_))) => (self.thunk(ty.clone()), *span),
+                _ => (ty.clone(), *span)
+            }).collect::<Vec<_>>();
+
+            let ret = self.check_call(&name, &operands, span);
+
+            return self.forced(ret);
+        }
+
+        let (left, right) = (self.solved(left), self.solved(right));
+        let err = error(format!("Operator '{}' cannot be applied to '{}' and '{}'.", op, left, right), span);
+
+        // The declaration can only be suggested if it can be written down
+        let err = match (left.declarable(), right.declarable()) {
+            (Some(left), Some(right)) => {
+                let prec = Operators::new().get(op).map_or(50, |fixity| fixity.prec);
+
+                err.with_help(format!("declare 'fn binary {} {} (a: {}, b: {})' to define it", op, prec, left, right))
+            },
+            _ => err
+        };
+
+        self.report(err);
+
+        Ty::Unknown
+    }
+
+    /// Checks the fields of a struct literal, whose errors are also reported by the evaluator.
+    fn check_struct_lit(&mut self, name: &str, fields: &[(String, Expr)], span: Span) -> Ty {
+        let def = match self.context.structs.get(name).copied() {
+            Some(Struct { fields: Fields::Named(ref names), .. }) => names,
+            Some(_) => {
+                for (_, init) in fields {
+                    self.check(init);
+                }
+
+                return Ty::Struct(name.to_owned());
+            },
+            None => {
+                self.report(error(format!("Unknown struct '{}'.", name), span));
+
+                return Ty::Unknown;
+            }
+        };
+
+        for (field, init) in fields {
+            match def.iter().find(|(name, _)| name == field) {
+                Some((_, ty)) => {
+                    let ty = self.signature(ty);
+
+                    self.expect(&ty, init);
+                },
+                None => { self.check(init); }
+            }
+        }
+
+        Ty::Struct(name.to_owned())
+    }
+
+    /// Returns the type of a field of a value of type `ty`.
+    fn check_field(&mut self, ty: Ty, member: &Member, target: Span, span: Span) -> Ty {
+        match ty {
+            Ty::Struct(ref name) => {
+                let def = match self.context.structs.get(name.as_str()).copied() {
+                    Some(def) => def,
+                    None => return Ty::Unknown
+                };
+
+                let field = match (&def.fields, member) {
+                    (Fields::Named(fields), Member::Named(member)) => fields.iter().find(|(name, _)| name == member).map(|(_, ty)| ty),
+                    (Fields::Tuple(types), Member::Index(index)) => types.get(*index),
+                    _ => None
+                };
+
+                match field {
+                    Some(ty) => self.signature(ty),
+                    None => {
+                        self.report(error(format!("Struct '{}' has no field '{}'.", name, member), span)
+                            .with_label(def.span, "struct defined here"));
+
+                        Ty::Unknown
+                    }
+                }
+            },
+
+            Ty::Tuple(mut types) => match member {
+                Member::Index(index) if *index < types.len() => types.swap_remove(*index),
+                _ => {
+                    self.report(error(format!("Tuple of {} element(s) has no field '{}'.", types.len(), member), span));
+
+                    Ty::Unknown
+                }
+            },
+
+            // A value whose type is not known yet is the only struct with the named field, if any;
+            // the number of elements of a tuple cannot be told from one of its positional fields.
+            Ty::Var(var) => {
+                let mut owners = self.context.structs.values().filter(|def| match (&def.fields, member) {
+                    (Fields::Named(fields), Member::Named(member)) => fields.iter().any(|(name, _)| name == member),
+                    _ => false
+                });
+
+                match (owners.next(), owners.next()) {
+                    (Some(def), None) => {
+                        let ty = Ty::Struct(def.name.clone());
+
+                        self.unify(&Ty::Var(var), &ty);
+                        self.check_field(ty, member, target, span)
+                    },
+                    (None, _) if matches!(member, Member::Named(_)) => {
+                        self.report(error(format!("No struct has a field '{}'.", member), span));
+
+                        Ty::Unknown
+                    },
+                    _ => Ty::Unknown
+                }
+            },
+
+            Ty::Unknown => Ty::Unknown,
+
+            ty => {
+                self.report(error(format!("Expected a struct or a tuple but found '{}'.", self.solved(&ty)), target));
+
+                Ty::Unknown
+            }
+        }
+    }
+
+    /// Checks that a pattern can match a value of type `ty`, and binds its variables.
+    fn check_pattern(&mut self, pattern: &Pattern, ty: &Ty) {
+        let shape = match pattern.kind {
+            PatternKind::Wildcard => return,
+            PatternKind::Binding(ref name) => return self.bind(name, ty.clone()),
+            PatternKind::Bool(_) => Ty::Bool,
+            PatternKind::Int(_, None) => Ty::IntLit,
+            PatternKind::Int(_, Some(ref suffix)) => Ty::Num(suffix.clone()),
+            PatternKind::Float(_) => Ty::Num("f64".to_owned()),
+            PatternKind::Char(_) => Ty::Char,
+            PatternKind::Str(_) => Ty::Str,
+            PatternKind::Tuple(ref patterns) => Ty::Tuple(patterns.iter().map(|_| self.fresh()).collect()),
+            PatternKind::TupleStruct { ref name, .. } | PatternKind::Struct { ref name, .. } => Ty::Struct(name.clone()),
+        };
+
+        // The variables of a mismatched pattern are still bound, so that they are not reported as unknown
+        let ty = if self.fits(&shape, ty) {
+            self.forced(ty.clone())
+        } else {
+            self.report_mismatch(ty, &shape, pattern.span);
+
+            Ty::Unknown
+        };
+
+        match pattern.kind {
+            PatternKind::Tuple(ref patterns) => {
+                let types = match ty {
+                    Ty::Tuple(types) => types,
+                    _ => vec![Ty::Unknown; patterns.len()]
+                };
+
+                for (pattern, ty) in patterns.iter().zip(&types) {
+                    self.check_pattern(pattern, ty);
+                }
+            },
+
+            // Patterns that do not fit their struct are reported by the exhaustiveness checker
+            PatternKind::TupleStruct { ref name, ref fields } => {
+                let types = match self.context.structs.get(name.as_str()).map(|def| &def.fields) {
+                    Some(Fields::Tuple(types)) if types.len() == fields.len() => types.iter().map(|ty| self.signature(ty)).collect(),
+                    _ => vec![Ty::Unknown; fields.len()]
+                };
+
+                for (pattern, ty) in fields.iter().zip(&types) {
+                    self.check_pattern(pattern, ty);
+                }
+            },
+
+            PatternKind::Struct { ref name, ref fields, .. } => {
+                for (field, pattern) in fields {
+                    let ty = match self.context.structs.get(name.as_str()).map(|def| &def.fields) {
+                        Some(Fields::Named(names)) => names.iter()
+                                                           .find(|(name, _)| name == field)
+                                                           .map_or(Ty::Unknown, |(_, ty)| self.signature(ty)),
+                        _ => Ty::Unknown
+                    };
+
+                    self.check_pattern(pattern, &ty);
+                }
+            },
+
+            _ => ()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{Item, Parser};
+
+    /// Infers the type of an expression, which can refer to the structs a program defines.
+    fn infer(program: &str, expr: &str) -> Result<String, Vec<String>> {
+        let program = Parser::new(program.to_owned()).parse_program().unwrap();
+        let structs = program.items.iter()
+                                   .filter_map(|item| match item {
+                                       Item::Struct(def) => Some((def.name.as_str(), def)),
+                                       _ => None
+                                   })
+                                   .collect();
+        let context = Context { structs, functions: HashMap::new(), builtins: HashSet::new() };
+
+        match Parser::new(expr.to_owned()).parse() {
+            Ok(Item::Function(Function { body: Some(expr), is_anon: true, .. })) => {
+                type_of(&expr, &context).map_err(|diagnostics| diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect())
+            },
+            result => panic!("expected an expression, found {:?}", result)
+        }
+    }
+
+    #[test]
+    fn indexed_values_are_lists_or_maps() {
+        assert_eq!(infer("", "|xs| xs[0]"), Ok("fn(['a]) -> 'a".to_owned()));
+        assert_eq!(infer("", "|m| m[\"a\"]"), Ok("fn({str => 'a}) -> 'a".to_owned()));
+        assert_eq!(infer("", "|xs| { xs[0] = 1u8; xs }"), Ok("fn([u8]) -> [u8]".to_owned()));
+    }
+
+    #[test]
+    fn fields_tell_the_struct_that_has_them() {
+        let structs = "struct P { x: u64, y: bool } struct Q { x: u64 }";
+
+        assert_eq!(infer(structs, "|p| p.y"), Ok("fn(P) -> bool".to_owned()));
+        assert_eq!(infer(structs, "|p| p.x"), Ok("fn('a) -> _".to_owned()));
+        assert_eq!(infer(structs, "|p| p.z"), Err(vec!["No struct has a field 'z'.".to_owned()]));
+    }
+}